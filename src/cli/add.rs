@@ -0,0 +1,26 @@
+use clap::ArgMatches;
+
+use crate::{
+    commands::add::Add, config::Config, package::metadata::DependencyKind, result::NanaResult,
+};
+
+#[tokio::main]
+pub async fn exec(arg_matches: &ArgMatches) -> NanaResult<()> {
+    let specs = arg_matches
+        .get_many::<String>("packages")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<String>>();
+
+    let kind = if arg_matches.get_flag("dev") {
+        DependencyKind::Development
+    } else if arg_matches.get_flag("optional") {
+        DependencyKind::Optional
+    } else {
+        DependencyKind::Production
+    };
+
+    let cmd = Add::new(Config::load()?, kind, arg_matches.get_flag("exact"));
+    cmd.run(&specs).await
+}
@@ -0,0 +1,21 @@
+use crate::{
+    commands::install::{Install, InstallOptions},
+    result::NanaResult,
+};
+use clap::ArgMatches;
+
+use super::install;
+
+/// `nana ci`, an install from scratch that never touches `nana.lock.yml`.
+#[tokio::main]
+pub async fn exec(arg_matches: &ArgMatches) -> NanaResult<()> {
+    let mut cmd = Install::new(
+        install::config(arg_matches)?,
+        InstallOptions {
+            frozen_lockfile: true,
+            clean: true,
+            ..install::options(arg_matches)
+        },
+    );
+    cmd.run().await
+}
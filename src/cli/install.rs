@@ -1,8 +1,55 @@
-use crate::{commands::install::Install, result::NanaResult};
+use crate::{
+    commands::install::{Install, InstallOptions},
+    config::Config,
+    package::metadata::DependencyKind,
+    result::NanaResult,
+};
 use clap::ArgMatches;
 
 #[tokio::main]
-pub async fn exec(_arg_matches: &ArgMatches) -> NanaResult<()> {
-    let mut cmd = Install::new();
+pub async fn exec(arg_matches: &ArgMatches) -> NanaResult<()> {
+    let mut cmd = Install::new(
+        config(arg_matches)?,
+        InstallOptions {
+            frozen_lockfile: arg_matches.get_flag("frozen-lockfile"),
+            ..options(arg_matches)
+        },
+    );
     cmd.run().await
 }
+
+/// Config with the network flags shared by `install` and `ci` applied.
+pub fn config(arg_matches: &ArgMatches) -> NanaResult<Config> {
+    let mut config = Config::load()?;
+
+    for flag in ["offline", "prefer-offline"] {
+        if arg_matches.get_flag(flag) {
+            config.set(flag, "true");
+        }
+    }
+
+    Ok(config)
+}
+
+/// Options shared by `install` and `ci`.
+pub fn options(arg_matches: &ArgMatches) -> InstallOptions {
+    let mut omit = vec![];
+
+    if arg_matches.get_flag("production") {
+        omit.push(DependencyKind::Development);
+    }
+
+    for kind in arg_matches.get_many::<String>("omit").into_iter().flatten() {
+        match kind.as_str() {
+            "dev" => omit.push(DependencyKind::Development),
+            "optional" => omit.push(DependencyKind::Optional),
+            _ => unreachable!(),
+        }
+    }
+
+    InstallOptions {
+        omit,
+        ignore_scripts: arg_matches.get_flag("ignore-scripts"),
+        ..InstallOptions::default()
+    }
+}
@@ -1,5 +1,8 @@
+mod add;
+mod ci;
 mod init;
 mod install;
+mod remove;
 mod run;
 mod version;
 
@@ -17,7 +20,119 @@ pub fn init() {
     let cmd = cmd.subcommand(Command::new("version").about("Output nana version"));
 
     // Install
-    let cmd = cmd.subcommand(Command::new("install").about("Install project dependencies"));
+    let cmd = cmd.subcommand(
+        Command::new("install")
+            .about("Install project dependencies")
+            .args(install_args())
+            .arg(
+                Arg::new("frozen-lockfile")
+                    .long("frozen-lockfile")
+                    .action(ArgAction::SetTrue)
+                    .help("Fail instead of updating an out of date nana.lock.yml"),
+            ),
+    );
+
+    // Ci
+    let cmd = cmd.subcommand(
+        Command::new("ci")
+            .about("Clean install of the exact dependencies locked in nana.lock.yml")
+            .args(install_args()),
+    );
+
+    // Add
+    let cmd = cmd.subcommand(
+        Command::new("add")
+            .about("Add dependencies to the project")
+            .arg(
+                Arg::new("packages")
+                    .action(ArgAction::Append)
+                    .required(true)
+                    .num_args(1..)
+                    .help("Packages to add, as `name` or `name@range`"),
+            )
+            .arg(
+                Arg::new("dev")
+                    .short('D')
+                    .long("save-dev")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("optional")
+                    .help("Save to devDependencies"),
+            )
+            .arg(
+                Arg::new("optional")
+                    .short('O')
+                    .long("save-optional")
+                    .action(ArgAction::SetTrue)
+                    .help("Save to optionalDependencies"),
+            )
+            .arg(
+                Arg::new("exact")
+                    .short('E')
+                    .long("exact")
+                    .action(ArgAction::SetTrue)
+                    .help("Save the exact version instead of a caret range"),
+            ),
+    );
+
+    // Remove
+    let cmd = cmd.subcommand(
+        Command::new("remove")
+            .about("Remove dependencies from the project")
+            .arg(
+                Arg::new("packages")
+                    .action(ArgAction::Append)
+                    .required(true)
+                    .num_args(1..)
+                    .help("Packages to remove"),
+            ),
+    );
+
+    // Run
+    let cmd = cmd.subcommand(
+        Command::new("run")
+            .about("Run a script defined in package.json")
+            .arg(Arg::new("script").required(true).help("Script name"))
+            .arg(
+                Arg::new("if-present")
+                    .long("if-present")
+                    .action(ArgAction::SetTrue)
+                    .help("Do nothing if the script is not defined"),
+            )
+            .arg(
+                Arg::new("recursive")
+                    .short('r')
+                    .long("recursive")
+                    .action(ArgAction::SetTrue)
+                    .help("Run the script in every workspace defining it"),
+            )
+            .arg(
+                Arg::new("filter")
+                    .long("filter")
+                    .action(ArgAction::Append)
+                    .value_name("PATTERN")
+                    .help("Only run in workspaces matching a name, a glob or a ./path"),
+            )
+            .arg(
+                Arg::new("parallel")
+                    .long("parallel")
+                    .action(ArgAction::SetTrue)
+                    .help("Run in every workspace at once, ignoring their dependencies"),
+            )
+            .arg(
+                Arg::new("concurrency")
+                    .long("concurrency")
+                    .value_parser(clap::value_parser!(usize))
+                    .value_name("N")
+                    .help("Maximum number of workspaces running at once"),
+            )
+            .arg(
+                Arg::new("args")
+                    .action(ArgAction::Append)
+                    .num_args(0..)
+                    .last(true)
+                    .help("Arguments forwarded to the script"),
+            ),
+    );
 
     // Init
     let cmd = cmd.subcommand(
@@ -37,13 +152,47 @@ pub fn init() {
     let result = match matches.subcommand() {
         Some(("version", _)) => version::exec(),
         Some(("install", arg_matches)) => install::exec(arg_matches),
+        Some(("ci", arg_matches)) => ci::exec(arg_matches),
+        Some(("add", arg_matches)) => add::exec(arg_matches),
+        Some(("remove", arg_matches)) => remove::exec(arg_matches),
         Some(("init", arg_matches)) => init::exec(arg_matches),
-        Some((ext, arg_matches)) => run::exec(ext, arg_matches),
+        Some(("run", arg_matches)) => run::exec(arg_matches),
+        Some((ext, arg_matches)) => run::exec_external(ext, arg_matches),
         _ => unreachable!(),
     };
 
     if let Err(error) = result {
         println!("Nana execution halted with error:");
-        println!("{}", style(error).red());
+        println!("{}", style(&error).red());
+
+        std::process::exit(error.exit_code());
     }
 }
+
+/// Options shared by `install` and `ci`.
+fn install_args() -> [Arg; 5] {
+    [
+        Arg::new("production")
+            .long("production")
+            .action(ArgAction::SetTrue)
+            .help("Skip devDependencies"),
+        Arg::new("omit")
+            .long("omit")
+            .action(ArgAction::Append)
+            .value_parser(["dev", "optional"])
+            .help("Dependency types to skip"),
+        Arg::new("ignore-scripts")
+            .long("ignore-scripts")
+            .action(ArgAction::SetTrue)
+            .help("Do not run lifecycle scripts"),
+        Arg::new("offline")
+            .long("offline")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("prefer-offline")
+            .help("Install from the cache and the package store only"),
+        Arg::new("prefer-offline")
+            .long("prefer-offline")
+            .action(ArgAction::SetTrue)
+            .help("Use cached metadata however stale, only fetch what is missing"),
+    ]
+}
@@ -0,0 +1,16 @@
+use clap::ArgMatches;
+
+use crate::{commands::remove::Remove, config::Config, result::NanaResult};
+
+#[tokio::main]
+pub async fn exec(arg_matches: &ArgMatches) -> NanaResult<()> {
+    let names = arg_matches
+        .get_many::<String>("packages")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<String>>();
+
+    let cmd = Remove::new(Config::load()?);
+    cmd.run(&names).await
+}
@@ -1,8 +1,83 @@
+use std::ffi::OsString;
+
 use clap::ArgMatches;
 
-use crate::{commands::run::RunScript, result::NanaResult};
+use crate::{
+    commands::run::{RunScript, WorkspaceOptions},
+    config::Config,
+    result::NanaResult,
+};
+
+const IF_PRESENT: &str = "--if-present";
+const ARGS_SEPARATOR: &str = "--";
+
+/// `nana run <script> [--if-present] [-r] [--filter <pattern>...] [--parallel]
+/// [--concurrency <n>] [-- <args>...]`, running in workspaces as soon as any
+/// workspace flag is given.
+pub fn exec(arg_matches: &ArgMatches) -> NanaResult<()> {
+    let name = arg_matches.get_one::<String>("script").unwrap();
+    let args = arg_matches
+        .get_many::<String>("args")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<String>>();
+
+    let filters = arg_matches
+        .get_many::<String>("filter")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<String>>();
+    let concurrency = arg_matches.get_one::<usize>("concurrency").copied();
+    let parallel = arg_matches.get_flag("parallel");
+    let if_present = arg_matches.get_flag("if-present");
+
+    let cmd = RunScript::new(Config::load()?);
+
+    if !arg_matches.get_flag("recursive")
+        && filters.is_empty()
+        && !parallel
+        && concurrency.is_none()
+    {
+        return cmd.run(name, &args, if_present);
+    }
+
+    let mut options = WorkspaceOptions {
+        filters,
+        parallel,
+        ..WorkspaceOptions::default()
+    };
+    if let Some(concurrency) = concurrency {
+        options.concurrency = concurrency;
+    }
+
+    cmd.run_workspaces(name, &args, if_present, &options)
+}
+
+/// `nana <script> [--if-present] [--] [<args>...]`, where everything but a
+/// leading `--if-present` and the first `--` is forwarded to the script.
+pub fn exec_external(name: &str, arg_matches: &ArgMatches) -> NanaResult<()> {
+    let mut args = arg_matches
+        .get_many::<OsString>("")
+        .into_iter()
+        .flatten()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+
+    let if_present = args.first().map(|arg| arg == IF_PRESENT).unwrap_or(false);
+    if if_present {
+        args.remove(0);
+    }
+
+    if args
+        .first()
+        .map(|arg| arg == ARGS_SEPARATOR)
+        .unwrap_or(false)
+    {
+        args.remove(0);
+    }
 
-pub fn exec(name: &str, _arg_matches: &ArgMatches) -> NanaResult<()> {
-    let cmd = RunScript::new();
-    cmd.run(name)
+    let cmd = RunScript::new(Config::load()?);
+    cmd.run(name, &args, if_present)
 }
@@ -0,0 +1,159 @@
+use console::style;
+
+use crate::{
+    commands::install::{
+        fetch::Fetcher,
+        specifier::{parse_spec, Specifier, ALIAS_PREFIX},
+        Install, InstallOptions,
+    },
+    config::Config,
+    package::{manifest::Manifest, metadata::DependencyKind},
+    result::NanaResult,
+};
+
+const ANY_VERSION: &str = "*";
+
+pub struct Add {
+    config: Config,
+    /// Shared with the install, so metadata is only fetched once.
+    fetcher: Fetcher,
+    kind: DependencyKind,
+    exact: bool,
+}
+
+impl Add {
+    pub fn new(config: Config, kind: DependencyKind, exact: bool) -> Self {
+        Self {
+            fetcher: Fetcher::new(&config),
+            config,
+            kind,
+            exact,
+        }
+    }
+}
+
+impl Add {
+    pub async fn run(&self, specs: &[String]) -> NanaResult<()> {
+        let mut manifest = Manifest::from_local_package()?;
+
+        for spec in specs {
+            let (name, requested) = parse_spec(spec);
+            let version_range = self.version_range(&name, requested.as_deref()).await?;
+
+            manifest.set_dependency(self.kind, &name, &version_range);
+            println!(
+                "Adding {}@{} to {}",
+                style(&name).bold(),
+                version_range,
+                self.kind.section()
+            );
+        }
+
+        manifest.save()?;
+
+        let mut install = Install::with_fetcher(
+            self.config.clone(),
+            InstallOptions::default(),
+            self.fetcher.clone(),
+        );
+        install.run().await
+    }
+
+    /// The range written to `package.json`, see `saved_spec`.
+    async fn version_range(&self, name: &str, requested: Option<&str>) -> NanaResult<String> {
+        let version = self
+            .fetcher
+            .fetch_dependency(name, requested.unwrap_or(ANY_VERSION))
+            .await?;
+
+        Ok(saved_spec(requested, &version.version, self.exact))
+    }
+}
+
+/// What is saved for the `requested` specifier resolved to `version`: a caret
+/// range on the version (the version itself when `exact` is set), unless an
+/// exact version or a dist-tag was requested, which are saved as is. Aliases
+/// keep their `npm:<name>@` prefix, and specifiers that are not registry ones
+/// are saved as requested.
+fn saved_spec(requested: Option<&str>, version: &str, exact: bool) -> String {
+    match requested.map(Specifier::parse) {
+        None | Some(Specifier::Range(_)) | Some(Specifier::Tag(_)) => {
+            saved_range(requested, version, exact)
+        }
+        Some(Specifier::Alias(target, _)) => {
+            let (_, requested) = parse_spec(
+                requested
+                    .unwrap_or_default()
+                    .trim_start_matches(ALIAS_PREFIX),
+            );
+            format!(
+                "{}{}@{}",
+                ALIAS_PREFIX,
+                target,
+                saved_range(requested.as_deref(), version, exact)
+            )
+        }
+        Some(_) => requested.unwrap_or_default().to_string(),
+    }
+}
+
+fn saved_range(requested: Option<&str>, version: &str, exact: bool) -> String {
+    match requested {
+        _ if exact => version.to_string(),
+        Some(requested) if Specifier::parse(requested).is_tag() || is_version(requested) => {
+            requested.to_string()
+        }
+        _ => format!("^{}", version),
+    }
+}
+
+/// Whether `range` only allows one version, e.g. `1.2.3` or `=1.2.3-rc.1`.
+fn is_version(range: &str) -> bool {
+    let version = range.trim().trim_start_matches(['=', 'v']);
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+
+    core.split('.').count() == 3
+        && core
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_caret_range_on_resolved_version() {
+        assert_eq!(saved_spec(None, "4.17.21", false), "^4.17.21");
+        assert_eq!(saved_spec(Some("^4"), "4.17.21", false), "^4.17.21");
+        assert_eq!(saved_spec(Some("4.x"), "4.17.21", false), "^4.17.21");
+        assert_eq!(saved_spec(Some("^4"), "4.17.21", true), "4.17.21");
+    }
+
+    #[test]
+    fn keeps_exact_versions_and_tags() {
+        assert_eq!(saved_spec(Some("4.17.0"), "4.17.0", false), "4.17.0");
+        assert_eq!(saved_spec(Some("next"), "5.0.0-rc.1", false), "next");
+        assert_eq!(saved_spec(Some("next"), "5.0.0-rc.1", true), "5.0.0-rc.1");
+    }
+
+    #[test]
+    fn keeps_alias_prefix_and_other_specifiers() {
+        assert_eq!(
+            saved_spec(Some("npm:lodash@^3"), "3.10.1", false),
+            "npm:lodash@^3.10.1"
+        );
+        assert_eq!(
+            saved_spec(Some("npm:lodash@^3"), "3.10.1", true),
+            "npm:lodash@3.10.1"
+        );
+        assert_eq!(
+            saved_spec(Some("file:../foo"), "1.0.0", false),
+            "file:../foo"
+        );
+        assert_eq!(
+            saved_spec(Some("github:org/foo#v1"), "1.0.0", false),
+            "github:org/foo#v1"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    package::{manifest::Manifest, metadata::MetadataVersion},
+    result::NanaResult,
+};
+
+use super::modules::NODE_MODULES_DIR;
+
+const BIN_DIR: &str = ".bin";
+
+/// Links the executables of every package in `dependencies` into the
+/// `.bin` directory of the `node_modules` it is installed in. When several
+/// packages provide the same executable, the project's direct dependencies
+/// win.
+pub fn link_bins(dependencies: &[(String, MetadataVersion)], direct: &[String]) -> NanaResult<()> {
+    let (direct, transitive): (Vec<_>, Vec<_>) = dependencies
+        .iter()
+        .partition(|(path, _)| direct.contains(path));
+
+    for (path, _) in transitive.into_iter().chain(direct) {
+        let manifest = match Manifest::from_dir(Path::new(path)) {
+            Ok(manifest) => manifest,
+            Err(_) => continue,
+        };
+
+        for (name, target) in manifest.bins() {
+            if !is_safe(&name, &target) {
+                continue;
+            }
+
+            link(path, &name, &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the links left dangling in the `.bin` directories next to the
+/// `removed` packages.
+pub fn unlink_stale(removed: &[String]) -> NanaResult<()> {
+    let dirs: HashSet<PathBuf> = removed.iter().map(|path| bin_dir(path)).collect();
+
+    for dir in dirs {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            // `exists` follows the link, so it is false for dangling ones
+            if entry.file_type()?.is_symlink() && !entry.path().exists() {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn link(path: &str, name: &str, target: &str) -> NanaResult<()> {
+    let target_path = Path::new(path).join(target);
+    if !target_path.is_file() {
+        return Ok(());
+    }
+
+    prepare_target(&target_path)?;
+
+    let dir = bin_dir(path);
+    std::fs::create_dir_all(&dir)?;
+
+    // Relative to the `.bin` directory, so the whole `node_modules` can move
+    let source = dir.join(name);
+    let destination = Path::new("..").join(package_dir_name(path)).join(target);
+
+    if std::fs::read_link(&source).ok().as_deref() == Some(destination.as_path()) {
+        return Ok(());
+    }
+
+    if source.symlink_metadata().is_ok() {
+        std::fs::remove_file(&source)?;
+    }
+
+    symlink(&destination, &source)
+}
+
+/// `.bin` directory of the `node_modules` the package at `path` lives in.
+fn bin_dir(path: &str) -> PathBuf {
+    let index = path.rfind(NODE_MODULES_DIR).unwrap_or(0);
+    Path::new(&path[..index])
+        .join(NODE_MODULES_DIR)
+        .join(BIN_DIR)
+}
+
+/// Directory name of the package at `path` inside its `node_modules`,
+/// including its scope if any.
+fn package_dir_name(path: &str) -> &str {
+    let index = path.rfind(NODE_MODULES_DIR).unwrap_or(0);
+    path[index + NODE_MODULES_DIR.len()..].trim_start_matches('/')
+}
+
+/// Guards against packages declaring bins that would escape `.bin` or their
+/// own directory.
+fn is_safe(name: &str, target: &str) -> bool {
+    let plain_name = !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\']);
+
+    let inner_target = Path::new(target)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    plain_name && inner_target
+}
+
+/// Makes the executable at `path` runnable. Package files are hard links
+/// into the store, so rather than changing the shared file, a fixed copy is
+/// renamed over the link.
+fn prepare_target(path: &Path) -> NanaResult<()> {
+    let content = std::fs::read(path)?;
+    let fixed = fix_shebang(&content);
+
+    if fixed.is_none() && is_executable(path)? {
+        return Ok(());
+    }
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.nana-tmp", file_name));
+    std::fs::write(&temp, fixed.as_deref().unwrap_or(&content))?;
+    make_executable(&temp)?;
+    std::fs::rename(&temp, path)?;
+
+    Ok(())
+}
+
+/// Scripts published from Windows may have a `#!/usr/bin/env node\r\n`
+/// shebang, which `env` fails to run. Returns the content with the line
+/// ending normalized, if needed.
+fn fix_shebang(content: &[u8]) -> Option<Vec<u8>> {
+    if !content.starts_with(b"#!") {
+        return None;
+    }
+
+    let end = content.iter().position(|b| *b == b'\n')?;
+    if end == 0 || content[end - 1] != b'\r' {
+        return None;
+    }
+
+    let mut fixed = content[..end - 1].to_vec();
+    fixed.extend_from_slice(&content[end..]);
+
+    Some(fixed)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> NanaResult<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Ok(std::fs::metadata(path)?.permissions().mode() & 0o111 == 0o111)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> NanaResult<bool> {
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> NanaResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    std::fs::set_permissions(path, permissions)?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> NanaResult<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(destination: &Path, source: &Path) -> NanaResult<()> {
+    Ok(std::os::unix::fs::symlink(destination, source)?)
+}
+
+#[cfg(windows)]
+fn symlink(destination: &Path, source: &Path) -> NanaResult<()> {
+    Ok(std::os::windows::fs::symlink_file(destination, source)?)
+}
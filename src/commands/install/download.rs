@@ -1,66 +1,382 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 
-use bytes::BytesMut;
+use bytes::Bytes;
 use flate2::read::GzDecoder;
 use futures::StreamExt;
 use tar::Archive;
 
-use crate::{package::metadata::MetadataVersion, result::NanaResult};
+use crate::{
+    config::{Config, NetworkMode, DEFAULT_REGISTRY},
+    package::{
+        manifest::Manifest,
+        metadata::{DependencyKind, MetadataVersion},
+    },
+    result::{NanaError, NanaResult, PackageError},
+};
 
-use super::{lock::Lock, Install};
+use super::{
+    fetch::Fetcher,
+    git,
+    integrity::VerifyingReader,
+    lifecycle, local,
+    modules::{self, NODE_MODULES_DIR},
+    specifier::Specifier,
+    store::{Index, Store},
+    tarball, Install,
+};
+
+/// Downloaded chunks waiting to be unpacked.
+const CHUNK_BUFFER: usize = 16;
+/// Suffix of the hidden directories packages are assembled in.
+const STAGING_SUFFIX: &str = ".staging";
+
+/// What a package is installed from.
+enum Contents {
+    /// Files already in the store.
+    Stored(Index),
+    /// Tarball that cannot be stored, e.g. a packed git checkout.
+    Tarball(Bytes),
+}
 
 impl Install {
-    pub async fn download(&self, lock: &Lock) -> NanaResult<()> {
+    /// Installs `dependencies` from the store, downloading the ones missing
+    /// there first, and returns the ones actually installed (optional ones
+    /// may be skipped). `link:` dependencies and
+    /// workspaces are symlinked instead, and not returned as their scripts
+    /// are not nana's to run.
+    pub async fn download(
+        &self,
+        dependencies: Vec<(String, MetadataVersion)>,
+    ) -> NanaResult<Vec<(String, MetadataVersion)>> {
         self.state()
             .progress_reset("Downloading dependencies")
             .await;
 
+        let (links, dependencies): (Vec<_>, Vec<_>) =
+            dependencies.into_iter().partition(|(_, meta_version)| {
+                matches!(
+                    Specifier::parse(&meta_version.dist.tarball),
+                    Specifier::Link(_) | Specifier::Workspace(_)
+                )
+            });
+
+        for (path, meta_version) in links {
+            if let Specifier::Link(target) | Specifier::Workspace(target) =
+                Specifier::parse(&meta_version.dist.tarball)
+            {
+                local::link(&path, &target)?;
+            }
+        }
+
+        let store = Store::new(&self.config);
+        let is_offline = self.config.network_mode() == NetworkMode::Offline;
         let mut tasks = vec![];
-        let dependencies = lock.flat_dependencies();
 
         self.state()
             .progress_set_length(dependencies.len() as u64)
             .await;
 
-        for meta_version in dependencies {
+        for (path, meta_version) in dependencies {
+            let store = &store;
             tasks.push(async move {
-                download_dist(&meta_version).await.unwrap();
+                if let Some(index) = store.index(&meta_version) {
+                    self.state().progress_increment(1).await;
+                    return Ok(Some((path, meta_version, Contents::Stored(index))));
+                }
+
+                let contents = match Specifier::parse(&meta_version.dist.tarball) {
+                    Specifier::Git(spec) => git::pack(
+                        &self.config,
+                        &spec,
+                        &meta_version,
+                        !self.options.ignore_scripts,
+                    )
+                    .await
+                    .map(Contents::Tarball),
+                    Specifier::LocalTarball(path) => {
+                        tarball::read_local(&path, &meta_version).map(Contents::Tarball)
+                    }
+                    Specifier::Directory(path) => {
+                        local::pack_directory(&path).map(Contents::Tarball)
+                    }
+                    // Offline, tarballs only come from the store
+                    _ if is_offline => Err(NanaError::Package(PackageError::NotAvailableOffline(
+                        meta_version.key(),
+                    ))),
+                    _ => download_to_store(&self.fetcher, &self.config, store, &meta_version)
+                        .await
+                        .map(Contents::Stored),
+                };
+
+                let contents = match contents {
+                    Ok(Contents::Tarball(bytes)) => {
+                        add_to_store(store, &meta_version, bytes).await?
+                    }
+                    Ok(contents) => contents,
+                    Err(error) if meta_version.kind == DependencyKind::Optional => {
+                        self.state()
+                            .warn(format!(
+                                "Skipping optional dependency '{}': {}",
+                                meta_version.key(),
+                                error
+                            ))
+                            .await;
+                        self.state().progress_increment(1).await;
+                        return Ok(None);
+                    }
+                    Err(error) => return Err(error),
+                };
+
                 self.state().progress_increment(1).await;
+
+                NanaResult::Ok(Some((path, meta_version, contents)))
             });
         }
 
-        futures::future::join_all(tasks).await;
+        // Every tarball is verified before anything is written to
+        // `node_modules`, so a single bad download leaves it untouched.
+        let tarballs = futures::future::try_join_all(tasks).await?;
+
+        // Packages are then assembled next to where they belong and only
+        // moved in place once all of them are, so a failure midway leaves
+        // `node_modules` untouched too
+        let mut staged = vec![];
+        for (path, meta_version, contents) in tarballs.into_iter().flatten() {
+            let staging = staging_path(&path);
+            let result = stage(&store, &staging, &meta_version, contents);
+            staged.push((path, meta_version, staging));
+
+            if let Err(error) = result {
+                for (_, _, staging) in &staged {
+                    let _ = std::fs::remove_dir_all(staging);
+                }
+                return Err(error);
+            }
+        }
+
+        let mut installed = vec![];
+        for (path, meta_version, staging) in staged {
+            replace_package_dir(&path, &staging)?;
+            installed.push((path, meta_version));
+        }
+
+        Ok(installed)
+    }
+}
+
+/// Streams the tarball of `meta_version` into the store: chunks are hashed,
+/// decompressed and unpacked on the blocking pool as they arrive, so the
+/// tarball is never held in memory. Its index is only saved once verified.
+async fn download_to_store(
+    fetcher: &Fetcher,
+    config: &Config,
+    store: &Store,
+    meta_version: &MetadataVersion,
+) -> NanaResult<Index> {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Bytes>(CHUNK_BUFFER);
+    let mut reader = VerifyingReader::new(ChunkReader::new(rx), meta_version)?;
+
+    let response = fetcher
+        .request_tarball(&tarball_url(config, meta_version))
+        .await?;
+    let store = store.clone();
+    let meta_version = meta_version.clone();
+
+    let unpack = tokio::task::spawn_blocking(move || {
+        let index = store.unpack(&mut reader);
+        // A tarball failing to unpack is most likely corrupt, which the
+        // integrity check reports better
+        reader.finish()?;
+        let index = index?;
+
+        store.save_index(&meta_version, &index)?;
+
+        Ok(index)
+    });
+
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        // The unpacking side only hangs up once done, its result tells why
+        if tx.send(chunk?).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+
+    unpack.await?
+}
 
-        Ok(())
+/// Stores a tarball already in memory, off the async runtime.
+async fn add_to_store(
+    store: &Store,
+    meta_version: &MetadataVersion,
+    bytes: Bytes,
+) -> NanaResult<Contents> {
+    let store = store.clone();
+    let meta_version = meta_version.clone();
+
+    tokio::task::spawn_blocking(move || {
+        Ok(match store.add(&meta_version, &bytes)? {
+            Some(index) => Contents::Stored(index),
+            None => Contents::Tarball(bytes),
+        })
+    })
+    .await?
+}
+
+/// Blocking reader over the chunks of a download, read until the sender
+/// hangs up.
+struct ChunkReader {
+    rx: tokio::sync::mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl ChunkReader {
+    fn new(rx: tokio::sync::mpsc::Receiver<Bytes>) -> Self {
+        Self {
+            rx,
+            chunk: Bytes::new(),
+        }
     }
 }
 
-async fn download_dist(meta_version: &MetadataVersion) -> NanaResult<()> {
-    let res = reqwest::get(&meta_version.dist.tarball).await?;
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
 
-    let mut stream = res.bytes_stream();
+        let read = buf.len().min(self.chunk.len());
+        buf[..read].copy_from_slice(&self.chunk.split_to(read));
 
-    let mut bytes = BytesMut::new();
-    while let Some(item) = stream.next().await {
-        let chunk = item?;
-        bytes.extend_from_slice(&chunk);
+        Ok(read)
     }
+}
+
+/// Tarballs published on the public registry are fetched from the registry
+/// configured for the package instead, as mirrors keep the same paths.
+fn tarball_url(config: &Config, meta_version: &MetadataVersion) -> String {
+    let tarball = &meta_version.dist.tarball;
 
+    match tarball.strip_prefix(DEFAULT_REGISTRY) {
+        Some(path) => format!("{}{}", config.registry(&meta_version.name), path),
+        None => tarball.clone(),
+    }
+}
+
+/// Where the package installed at `path` is assembled, next to it so it can
+/// be renamed in place.
+fn staging_path(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((parent, name)) => format!("{}/.{}{}", parent, name, STAGING_SUFFIX),
+        None => format!(".{}{}", path, STAGING_SUFFIX),
+    }
+}
+
+fn stage(
+    store: &Store,
+    staging: &str,
+    meta_version: &MetadataVersion,
+    contents: Contents,
+) -> NanaResult<()> {
+    if Path::new(staging).exists() {
+        std::fs::remove_dir_all(staging)?;
+    }
+    std::fs::create_dir_all(staging)?;
+
+    match contents {
+        Contents::Stored(index) => link_from_store(store, staging, &index)?,
+        Contents::Tarball(bytes) => extract_dist(staging, bytes)?,
+    }
+
+    modules::write_source(staging, meta_version)
+}
+
+/// Installs the stored files of `index` at `staging`, copied instead of
+/// linked for packages with install scripts.
+fn link_from_store(store: &Store, staging: &str, index: &Index) -> NanaResult<()> {
+    store.link(index, staging)?;
+
+    // Install scripts may change the files of their package in place, which
+    // must not reach the store through the hard links
+    let has_scripts = Manifest::from_dir(Path::new(staging))
+        .is_ok_and(|manifest| lifecycle::has_install_scripts(&manifest, staging));
+    if has_scripts {
+        std::fs::remove_dir_all(staging)?;
+        std::fs::create_dir_all(staging)?;
+        store.copy(index, staging)?;
+    }
+
+    Ok(())
+}
+
+/// Moves the package assembled at `staging` to `path`, replacing the
+/// previously installed version but keeping its nested `node_modules`, which
+/// is managed separately.
+fn replace_package_dir(path: &str, staging: &str) -> NanaResult<()> {
+    let package_dir = Path::new(path);
+
+    if package_dir.is_symlink() {
+        // Previously linked, the link target must be left alone
+        local::remove_link(package_dir)?;
+    } else if package_dir.is_dir() {
+        let nested = package_dir.join(NODE_MODULES_DIR);
+        if nested.is_dir() {
+            move_entries(&nested, &Path::new(staging).join(NODE_MODULES_DIR))?;
+        }
+        std::fs::remove_dir_all(package_dir)?;
+    } else if package_dir.exists() {
+        std::fs::remove_file(package_dir)?;
+    }
+
+    std::fs::rename(staging, package_dir)?;
+
+    Ok(())
+}
+
+/// Moves the entries of `from` into `to`, keeping the ones `to` already has.
+fn move_entries(from: &Path, to: &Path) -> NanaResult<()> {
+    if !to.exists() {
+        std::fs::rename(from, to)?;
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(from)?.filter_map(|e| e.ok()) {
+        let destination = to.join(entry.file_name());
+        if destination.symlink_metadata().is_err() {
+            std::fs::rename(entry.path(), destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_dist(path: &str, bytes: Bytes) -> NanaResult<()> {
     let mut content = Cursor::new(bytes);
 
     let decoder = GzDecoder::new(&mut content);
     let mut archive = Archive::new(decoder);
 
+    let package_path = PathBuf::from(path);
+
     for mut entry in archive.entries()?.filter_map(|e| e.ok()) {
-        let package_path: PathBuf = format!("node_modules/{}/", meta_version.name).into();
+        // Contents are wrapped in a single top-level directory, which is
+        // usually `package/` but not always (e.g. `@types/*` use the name)
         let entry_path = entry.path()?;
-        let file_path: PathBuf = if entry_path.starts_with("package") {
-            entry_path.strip_prefix("package")?.to_owned()
-        } else {
-            entry_path.into()
-        };
+        let file_path: PathBuf = entry_path.components().skip(1).collect();
+
+        if file_path.as_os_str().is_empty()
+            || !file_path
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            continue;
+        }
 
         let path = package_path.join(file_path);
         std::fs::create_dir_all(path.parent().unwrap())?;
@@ -1,46 +1,255 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
 use crate::{
+    config::{Config, NetworkMode},
     package::metadata::{Metadata, MetadataVersion},
-    result::NanaResult,
+    result::{NanaError, NanaResult, PackageError},
 };
 
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
 use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache};
-use reqwest::Client;
-use reqwest_middleware::ClientBuilder;
+use reqwest::{Client, Response, StatusCode};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use semver_rs::{Range, Version};
+use tokio::sync::OnceCell;
 
-use super::InstallCommand;
+use super::{git, local, specifier::Specifier, store::Store, tarball, InstallCommand};
 
-const CACHE_DIR: &str = ".nana/cache/http";
-const REGISTRY_URL: &str = "https://registry.npmjs.org/";
+/// Metadata responses, below the cache directory.
+const HTTP_CACHE_DIR: &str = "http";
+const LATEST_TAG: &str = "latest";
 
 const HEADER_ACCEPT: &str =
     "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*";
 
-pub async fn fetch_metadata(
-    name: &String,
-    version_range: &String,
-    tx: tokio::sync::mpsc::UnboundedSender<InstallCommand>,
-) -> NanaResult<()> {
-    let version = fetch_dependency(name, version_range).await?;
+/// Metadata of a package, set once fetched. Concurrent requests for the same
+/// name wait for the first one.
+type MetadataCell = Arc<OnceCell<Arc<Metadata>>>;
+
+/// HTTP client shared by every request of a command, so connections are
+/// pooled, along with the metadata fetched so far. The metadata of a package
+/// is fetched once, however many ranges it is requested with.
+#[derive(Clone)]
+pub struct Fetcher {
+    config: Config,
+    /// Goes through the HTTP cache, for metadata.
+    client: ClientWithMiddleware,
+    /// Tarballs end up in the package store, they are not cached.
+    tarball_client: Client,
+    metadata: Arc<Mutex<HashMap<String, MetadataCell>>>,
+}
+
+impl Fetcher {
+    pub fn new(config: &Config) -> Self {
+        let mode = match config.network_mode() {
+            NetworkMode::Online => CacheMode::Default,
+            NetworkMode::PreferOffline => CacheMode::ForceCache,
+            NetworkMode::Offline => CacheMode::OnlyIfCached,
+        };
+
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode,
+                manager: CACacheManager {
+                    path: config
+                        .cache_dir()
+                        .join(HTTP_CACHE_DIR)
+                        .to_string_lossy()
+                        .into_owned(),
+                },
+                options: None,
+            }))
+            .build();
+
+        Self {
+            config: config.clone(),
+            client,
+            tarball_client: Client::new(),
+            metadata: Arc::default(),
+        }
+    }
+
+    pub async fn fetch_metadata(
+        &self,
+        name: &str,
+        version_range: &str,
+        optional: bool,
+        tx: tokio::sync::mpsc::UnboundedSender<InstallCommand>,
+    ) -> NanaResult<()> {
+        let version = self.fetch_dependency(name, version_range).await?;
 
-    tx.send(InstallCommand::AddPackage(
-        name.clone(),
-        version_range.clone(),
-        version,
-    ))?;
+        tx.send(InstallCommand::AddPackage(
+            name.to_string(),
+            version_range.to_string(),
+            Box::new(version),
+            optional,
+        ))?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_dependency(
+        &self,
+        name: &str,
+        version_range: &str,
+    ) -> NanaResult<MetadataVersion> {
+        let is_offline = self.config.network_mode() == NetworkMode::Offline;
+
+        match Specifier::parse(version_range) {
+            // Resolving a git reference or hashing a remote tarball takes the network
+            Specifier::Git(_) | Specifier::Tarball(_) if is_offline => Err(NanaError::Package(
+                PackageError::NotAvailableOffline(name.to_string()),
+            )),
+            Specifier::Range(_) | Specifier::Tag(_) => {
+                self.fetch_registry_dependency(name, version_range).await
+            }
+            Specifier::Alias(target, version_range) => {
+                self.fetch_registry_dependency(&target, &version_range)
+                    .await
+            }
+            Specifier::Git(spec) => git::resolve(&self.config, name, &spec).await,
+            Specifier::Tarball(url) => {
+                let store = Store::new(&self.config);
+                tarball::resolve_remote(self, &store, name, &url).await
+            }
+            Specifier::LocalTarball(path) => tarball::resolve_local(name, &path),
+            Specifier::Directory(path) => local::resolve_directory(name, &path),
+            Specifier::Link(path) => local::resolve_link(name, &path),
+            Specifier::Workspace(_) => Err(NanaError::Package(PackageError::WorkspaceNotFound(
+                name.to_string(),
+            ))),
+        }
+    }
+
+    /// Downloads `url`, authenticated if credentials are configured for it.
+    pub async fn fetch_tarball(&self, url: &str) -> NanaResult<Bytes> {
+        let mut stream = self.request_tarball(url).await?.bytes_stream();
+
+        let mut bytes = BytesMut::new();
+        while let Some(item) = stream.next().await {
+            let chunk = item?;
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(bytes.freeze())
+    }
+
+    /// Requests `url`, leaving the body to be streamed.
+    pub async fn request_tarball(&self, url: &str) -> NanaResult<Response> {
+        let mut request = self.tarball_client.get(url);
+
+        if let Some(auth) = self.config.auth(url) {
+            request = request.header("authorization", auth.header());
+        }
+
+        Ok(request.send().await?.error_for_status()?)
+    }
+
+    async fn fetch_registry_dependency(
+        &self,
+        name: &str,
+        version_range: &str,
+    ) -> NanaResult<MetadataVersion> {
+        let metadata = self.package_metadata(name).await?;
+
+        match find_version(&metadata, version_range)? {
+            // Full documents list dev dependencies, which are not installed
+            Some(version) => Ok(MetadataVersion {
+                dev_dependencies: None,
+                ..version
+            }),
+            None => Err(NanaError::Package(PackageError::VersionNotFound(
+                name.to_string(),
+                version_range.to_string(),
+            ))),
+        }
+    }
+
+    /// Metadata of `name`, fetched on first use only. Failures are not
+    /// memoized, the next request tries again.
+    async fn package_metadata(&self, name: &str) -> NanaResult<Arc<Metadata>> {
+        let cell = self
+            .metadata
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+
+        let metadata = cell
+            .get_or_try_init(|| async { self.fetch_package_metadata(name).await.map(Arc::new) })
+            .await?;
+
+        Ok(metadata.clone())
+    }
+
+    /// Fetches the metadata of `name` through the HTTP cache. Offline, cache
+    /// misses fail instead of reaching the registry.
+    async fn fetch_package_metadata(&self, name: &str) -> NanaResult<Metadata> {
+        let url = metadata_url(&self.config, name);
+        let mut request = self.client.get(&url).header("accept", HEADER_ACCEPT);
+
+        if let Some(auth) = self.config.auth(&url) {
+            request = request.header("authorization", auth.header());
+        }
 
-    Ok(())
+        let response = request.send().await?;
+
+        // The cache answers misses with a gateway timeout when offline
+        if self.config.network_mode() == NetworkMode::Offline
+            && response.status() == StatusCode::GATEWAY_TIMEOUT
+        {
+            return Err(NanaError::Package(PackageError::NotAvailableOffline(
+                name.to_string(),
+            )));
+        }
+
+        let result = response.error_for_status()?.json::<Metadata>().await?;
+
+        Ok(result)
+    }
 }
 
-async fn fetch_dependency(name: &String, version_range: &String) -> NanaResult<MetadataVersion> {
-    let metadata = fetch_package_metadata(name).await?;
-    let mut meta_versions = parse_metadata(&metadata, version_range)?;
-    let best_version = find_best_matching_version(&mut meta_versions);
+/// Picks the version `version_range` resolves to: the tagged one for a
+/// dist-tag, otherwise the highest matching version. Like npm, the `latest`
+/// tag wins whenever it satisfies the range, so `*` does not pick a newer
+/// prerelease.
+fn find_version(metadata: &Metadata, version_range: &str) -> NanaResult<Option<MetadataVersion>> {
+    if let Specifier::Tag(tag) = Specifier::parse(version_range) {
+        return Ok(metadata
+            .dist_tags
+            .get(&tag)
+            .and_then(|version| metadata.versions.get(version))
+            .cloned());
+    }
+
+    let latest = metadata
+        .dist_tags
+        .get(LATEST_TAG)
+        .and_then(|version| metadata.versions.get(version));
+
+    if let Some(latest) = latest {
+        let is_any = version_range.trim().is_empty() || version_range.trim() == "*";
+
+        if is_any
+            || Range::new(version_range)
+                .parse()?
+                .test(&Version::new(&latest.version).parse()?)
+        {
+            return Ok(Some(latest.clone()));
+        }
+    }
+
+    let mut meta_versions = parse_metadata(metadata, version_range)?;
 
-    Ok(best_version.clone())
+    Ok(find_best_matching_version(&mut meta_versions).cloned())
 }
 
-fn parse_metadata(metadata: &Metadata, version_range: &String) -> NanaResult<Vec<MetadataVersion>> {
+fn parse_metadata(metadata: &Metadata, version_range: &str) -> NanaResult<Vec<MetadataVersion>> {
     let range = Range::new(version_range).parse()?;
     let mut result: Vec<MetadataVersion> = vec![];
 
@@ -55,9 +264,9 @@ fn parse_metadata(metadata: &Metadata, version_range: &String) -> NanaResult<Vec
     Ok(result)
 }
 
-fn find_best_matching_version(list: &'_ mut Vec<MetadataVersion>) -> &'_ MetadataVersion {
+fn find_best_matching_version(list: &mut [MetadataVersion]) -> Option<&MetadataVersion> {
     match list.len() {
-        1 => list.first().unwrap(),
+        0 | 1 => list.first(),
         _ => {
             list.sort_by(|a, b| {
                 Version::new(&b.version)
@@ -67,29 +276,108 @@ fn find_best_matching_version(list: &'_ mut Vec<MetadataVersion>) -> &'_ Metadat
                     .unwrap()
             });
 
-            list.first().unwrap()
+            list.first()
         }
     }
 }
 
-async fn fetch_package_metadata(name: &String) -> NanaResult<Metadata> {
-    let client = ClientBuilder::new(Client::new())
-        .with(Cache(HttpCache {
-            mode: CacheMode::Default,
-            manager: CACacheManager {
-                path: CACHE_DIR.into(),
-            },
-            options: None,
-        }))
-        .build();
-
-    let result = client
-        .get(format!("{}{}", REGISTRY_URL, name))
-        .header("accept", HEADER_ACCEPT)
-        .send()
-        .await?
-        .json::<Metadata>()
-        .await?;
+/// The registry expects the `/` of scoped names (`@scope/name`) escaped.
+fn metadata_url(config: &Config, name: &str) -> String {
+    format!("{}{}", config.registry(name), name.replace('/', "%2f"))
+}
 
-    Ok(result)
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// `pkg` with versions `1.0.0`, `1.1.0` and `2.0.0-rc.1`, tagged `tags`.
+    fn metadata(tags: &[(&str, &str)]) -> Metadata {
+        let versions: serde_json::Map<String, serde_json::Value> = ["1.0.0", "1.1.0", "2.0.0-rc.1"]
+            .iter()
+            .map(|version| {
+                let dist =
+                    json!({ "tarball": format!("https://r.example/pkg/-/pkg-{}.tgz", version) });
+                let meta_version = json!({ "name": "pkg", "version": version, "dist": dist });
+                (version.to_string(), meta_version)
+            })
+            .collect();
+        let tags: serde_json::Map<String, serde_json::Value> = tags
+            .iter()
+            .map(|(tag, version)| (tag.to_string(), json!(version)))
+            .collect();
+
+        serde_json::from_value(json!({ "dist-tags": tags, "versions": versions })).unwrap()
+    }
+
+    fn found(metadata: &Metadata, version_range: &str) -> Option<String> {
+        find_version(metadata, version_range)
+            .unwrap()
+            .map(|meta_version| meta_version.version)
+    }
+
+    #[test]
+    fn resolves_dist_tags() {
+        let metadata = metadata(&[("latest", "1.1.0"), ("next", "2.0.0-rc.1")]);
+
+        assert_eq!(found(&metadata, "next").as_deref(), Some("2.0.0-rc.1"));
+        assert_eq!(found(&metadata, "latest").as_deref(), Some("1.1.0"));
+        assert_eq!(found(&metadata, "beta"), None);
+    }
+
+    #[test]
+    fn prefers_latest_when_it_satisfies_the_range() {
+        let metadata = metadata(&[("latest", "1.0.0")]);
+
+        assert_eq!(found(&metadata, "^1.0.0").as_deref(), Some("1.0.0"));
+        assert_eq!(found(&metadata, "^1.1.0").as_deref(), Some("1.1.0"));
+        assert_eq!(found(&metadata, "^3.0.0"), None);
+    }
+
+    #[test]
+    fn any_range_does_not_pick_prereleases() {
+        let tagged = metadata(&[("latest", "1.1.0")]);
+        assert_eq!(found(&tagged, "*").as_deref(), Some("1.1.0"));
+        assert_eq!(found(&tagged, "").as_deref(), Some("1.1.0"));
+
+        let untagged = metadata(&[]);
+        assert_eq!(found(&untagged, "*").as_deref(), Some("1.1.0"));
+    }
+
+    #[tokio::test]
+    async fn missing_tag_is_not_found() {
+        let fetcher = Fetcher::new(&Config::default());
+        let cell = OnceCell::new_with(Some(Arc::new(metadata(&[("latest", "1.1.0")]))));
+        fetcher
+            .metadata
+            .lock()
+            .unwrap()
+            .insert("pkg".to_string(), Arc::new(cell));
+
+        let result = fetcher.fetch_dependency("pkg", "beta").await;
+        assert!(matches!(
+            result,
+            Err(NanaError::Package(PackageError::VersionNotFound(name, range)))
+                if name == "pkg" && range == "beta"
+        ));
+    }
+
+    #[tokio::test]
+    async fn refuses_remote_sources_offline() {
+        let mut config = Config::default();
+        config.set("offline", "true");
+        let fetcher = Fetcher::new(&config);
+
+        for spec in [
+            "github:org/x",
+            "git+https://g.example/x.git#main",
+            "https://r.example/x/-/x-1.0.0.tgz",
+        ] {
+            assert!(matches!(
+                fetcher.fetch_dependency("x", spec).await,
+                Err(NanaError::Package(PackageError::NotAvailableOffline(name))) if name == "x"
+            ));
+        }
+    }
 }
@@ -0,0 +1,430 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bytes::Bytes;
+use git2::{
+    build::CheckoutBuilder, Cred, CredentialType, FetchOptions, Oid, RemoteCallbacks, Repository,
+};
+use semver_rs::{Range, Version};
+
+use crate::{
+    config::{Config, NetworkMode},
+    package::{
+        manifest::Manifest,
+        metadata::{Dist, MetadataVersion},
+        PACKAGE_NAME,
+    },
+    result::{NanaError, NanaResult, PackageError},
+};
+
+use super::packlist;
+
+/// Bare clones and checkouts, below the cache directory.
+const GIT_CACHE_DIR: &str = "git";
+const CHECKOUTS_DIR: &str = "checkouts";
+const SEMVER_PREFIX: &str = "semver:";
+const MAX_AUTH_ATTEMPTS: usize = 3;
+
+/// Distinguishes the checkouts made concurrently by this process.
+static CHECKOUT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Hosted git shortcuts and the URL their `user/repo` path is appended to.
+const HOSTS: [(&str, &str); 3] = [
+    ("github:", "https://github.com/"),
+    ("gitlab:", "https://gitlab.com/"),
+    ("bitbucket:", "https://bitbucket.org/"),
+];
+
+/// Dependency fetched from a git repository, e.g. `github:org/repo#v1.2.3`,
+/// `git+ssh://git@host/repo.git#<commit>` or `org/repo#semver:^2`.
+#[derive(Debug, Clone)]
+pub struct GitSpec {
+    /// URL the repository is cloned from.
+    pub url: String,
+    /// Branch, tag, commit or `semver:<range>`, the default branch if unset.
+    pub committish: Option<String>,
+}
+
+impl GitSpec {
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (source, committish) = match spec.split_once('#') {
+            Some((source, committish)) => (source, Some(committish.to_string())),
+            None => (spec, None),
+        };
+
+        let url = if let Some(url) = source.strip_prefix("git+") {
+            url.to_string()
+        } else if source.starts_with("git://") {
+            source.to_string()
+        } else if let Some((prefix, host)) = HOSTS.iter().find(|(p, _)| source.starts_with(p)) {
+            hosted_url(host, &source[prefix.len()..])
+        } else if is_shorthand(source) {
+            hosted_url(HOSTS[0].1, source)
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            url,
+            committish: committish.filter(|committish| !committish.is_empty()),
+        })
+    }
+
+    /// Source recorded in the lock once resolved to commit `sha`.
+    fn resolved(&self, sha: &str) -> String {
+        format!("git+{}#{}", self.url, sha)
+    }
+}
+
+/// Resolves `spec` to a commit and reads the manifest found there. The
+/// commit ends up in `dist.tarball`, so the lock pins it.
+pub async fn resolve(config: &Config, name: &str, spec: &GitSpec) -> NanaResult<MetadataVersion> {
+    let name = name.to_string();
+    let spec = spec.clone();
+    let cache_dir = config.cache_dir().join(GIT_CACHE_DIR);
+
+    tokio::task::spawn_blocking(move || {
+        let repo = fetch_repository(&cache_dir, &spec.url)?;
+        let oid = match find_commit(&repo, spec.committish.as_deref())? {
+            Some(oid) => oid,
+            None => {
+                return Err(NanaError::Package(PackageError::VersionNotFound(
+                    name,
+                    spec.committish.unwrap_or_default(),
+                )))
+            }
+        };
+
+        let dist = Dist {
+            tarball: spec.resolved(&oid.to_string()),
+            shasum: None,
+            integrity: None,
+        };
+
+        let tree = repo.find_commit(oid)?.tree()?;
+        let blob = tree
+            .get_path(Path::new(PACKAGE_NAME))?
+            .to_object(&repo)?
+            .peel_to_blob()?;
+
+        MetadataVersion::from_manifest(&name, blob.content(), dist)
+    })
+    .await?
+}
+
+/// Checks out the locked commit of `spec`, runs its `prepare` script when
+/// `prepare` is set, and packs the result like `npm pack` would. Offline,
+/// the commit has to be in the cached clone already.
+pub async fn pack(
+    config: &Config,
+    spec: &GitSpec,
+    meta_version: &MetadataVersion,
+    prepare: bool,
+) -> NanaResult<Bytes> {
+    let spec = spec.clone();
+    let key = meta_version.key();
+    let cache_dir = config.cache_dir().join(GIT_CACHE_DIR);
+    let network_mode = config.network_mode();
+
+    tokio::task::spawn_blocking(move || {
+        let sha = spec.committish.clone().unwrap_or_default();
+        let oid = Oid::from_str(&sha)?;
+
+        let repo = match Repository::open_bare(cache_path(&cache_dir, &spec.url)) {
+            Ok(repo) if repo.find_commit(oid).is_ok() => repo,
+            _ if network_mode == NetworkMode::Offline => {
+                return Err(NanaError::Package(PackageError::NotAvailableOffline(key)))
+            }
+            _ => fetch_repository(&cache_dir, &spec.url)?,
+        };
+
+        // libgit2 fails to create nested directories under a relative target.
+        // Each pack gets its own checkout, as other installs may pack the
+        // same commit at the same time.
+        let dir = std::env::current_dir()?
+            .join(&cache_dir)
+            .join(CHECKOUTS_DIR)
+            .join(format!(
+                "{}-{}-{}",
+                sha,
+                std::process::id(),
+                CHECKOUT_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+        std::fs::create_dir_all(&dir)?;
+
+        let result = checkout(&repo, oid, &dir).and_then(|_| {
+            if prepare && Manifest::from_dir(&dir)?.script("prepare").is_some() {
+                run_prepare(&key, &dir, network_mode)?;
+            }
+
+            packlist::pack(&dir)
+        });
+
+        std::fs::remove_dir_all(&dir)?;
+
+        result
+    })
+    .await?
+}
+
+/// Opens the cached bare clone of `url`, creating it if needed, and fetches
+/// every branch and tag. `HEAD` is detached at the remote's default branch.
+fn fetch_repository(cache_dir: &Path, url: &str) -> NanaResult<Repository> {
+    let path = cache_path(cache_dir, url);
+    let repo = match Repository::open_bare(&path) {
+        Ok(repo) => repo,
+        Err(_) => {
+            std::fs::create_dir_all(&path)?;
+            Repository::init_bare(&path)?
+        }
+    };
+
+    let head = {
+        let mut remote = repo.remote_anonymous(url)?;
+        remote.fetch(
+            &["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"],
+            Some(&mut fetch_options()),
+            None,
+        )?;
+
+        let head = remote
+            .list()?
+            .iter()
+            .find(|head| head.name() == "HEAD")
+            .map(|head| head.oid());
+        head
+    };
+
+    if let Some(head) = head {
+        repo.set_head_detached(head)?;
+    }
+
+    Ok(repo)
+}
+
+/// Commit `committish` points to: a branch, tag or commit, the highest tag
+/// satisfying a `semver:` range, or the default branch when unset.
+fn find_commit(repo: &Repository, committish: Option<&str>) -> NanaResult<Option<Oid>> {
+    let revision = match committish {
+        None => return Ok(Some(repo.head()?.peel_to_commit()?.id())),
+        Some(committish) => match committish.strip_prefix(SEMVER_PREFIX) {
+            Some(range) => match semver_tag(repo, range)? {
+                Some(tag) => format!("refs/tags/{}", tag),
+                None => return Ok(None),
+            },
+            None => committish.to_string(),
+        },
+    };
+
+    match repo.revparse_single(&revision) {
+        Ok(object) => Ok(Some(object.peel_to_commit()?.id())),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Highest tag (with or without a leading `v`) satisfying `range`.
+fn semver_tag(repo: &Repository, range: &str) -> NanaResult<Option<String>> {
+    let range = Range::new(range).parse()?;
+    let tags = repo.tag_names(None)?;
+
+    let best = tags
+        .iter()
+        .flatten()
+        .filter_map(|tag| {
+            let version = Version::new(tag.trim_start_matches('v')).parse().ok()?;
+            range.test(&version).then_some((version, tag))
+        })
+        .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    Ok(best.map(|(_, tag)| tag.to_string()))
+}
+
+fn checkout(repo: &Repository, oid: Oid, dir: &Path) -> NanaResult<()> {
+    let commit = repo.find_commit(oid)?;
+    let mut options = CheckoutBuilder::new();
+    options.target_dir(dir).update_index(false).force();
+
+    repo.checkout_tree(commit.as_object(), Some(&mut options))?;
+
+    Ok(())
+}
+
+/// `prepare` usually builds the package, so the dev dependencies it needs are
+/// installed first, like npm does. nana's own install runs the script, using
+/// the network like the install it is part of.
+fn run_prepare(key: &str, dir: &Path, network_mode: NetworkMode) -> NanaResult<()> {
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command.arg("install").current_dir(dir);
+
+    match network_mode {
+        NetworkMode::Online => {}
+        NetworkMode::PreferOffline => {
+            command.arg("--prefer-offline");
+        }
+        NetworkMode::Offline => {
+            command.arg("--offline");
+        }
+    }
+
+    let output = command.output()?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(NanaError::Package(PackageError::LifecycleFailed(
+        key.to_string(),
+        "prepare".to_string(),
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+    )))
+}
+
+/// Asks the ssh agent or git's credential helpers, giving up after a few
+/// rejected attempts instead of looping forever.
+fn fetch_options<'a>() -> FetchOptions<'a> {
+    let mut attempts = 0;
+    let mut callbacks = RemoteCallbacks::new();
+
+    callbacks.credentials(move |url, username, allowed| {
+        attempts += 1;
+        if attempts > MAX_AUTH_ATTEMPTS {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+
+        let username = username.unwrap_or("git");
+        if allowed.contains(CredentialType::USERNAME) {
+            Cred::username(username)
+        } else if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username)
+        } else {
+            Cred::credential_helper(&git2::Config::open_default()?, url, Some(username))
+        }
+    });
+
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    options
+}
+
+fn cache_path(cache_dir: &Path, url: &str) -> PathBuf {
+    let key: String = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+
+    cache_dir.join(key)
+}
+
+fn hosted_url(host: &str, path: &str) -> String {
+    match path.ends_with(".git") {
+        true => format!("{}{}", host, path),
+        false => format!("{}{}.git", host, path),
+    }
+}
+
+/// `user/repo`, GitHub's shorthand.
+fn is_shorthand(source: &str) -> bool {
+    match source.split_once('/') {
+        Some((user, repo)) => {
+            !user.is_empty()
+                && !repo.is_empty()
+                && !repo.contains('/')
+                && !source.contains(':')
+                && !source.starts_with(['@', '.', '~'])
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{IndexAddOption, Signature};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Writes `files` in the work tree of `repo` and commits them on `HEAD`.
+    fn commit(repo: &Repository, files: &[(&str, &str)]) -> Oid {
+        let dir = repo.workdir().unwrap();
+        for (path, content) in files {
+            std::fs::write(dir.join(path), content).unwrap();
+        }
+
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let signature = Signature::now("nana", "nana@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "commit",
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    fn tag(repo: &Repository, name: &str, oid: Oid) {
+        let object = repo.find_object(oid, None).unwrap();
+        repo.tag_lightweight(name, &object, false).unwrap();
+    }
+
+    #[test]
+    fn parses_hosted_shortcuts() {
+        let spec = GitSpec::parse("github:org/repo#v1.2.3").unwrap();
+        assert_eq!(spec.url, "https://github.com/org/repo.git");
+        assert_eq!(spec.committish.as_deref(), Some("v1.2.3"));
+
+        let spec = GitSpec::parse("org/repo#semver:^2").unwrap();
+        assert_eq!(spec.url, "https://github.com/org/repo.git");
+        assert_eq!(spec.committish.as_deref(), Some("semver:^2"));
+
+        let spec = GitSpec::parse("gitlab:org/repo.git").unwrap();
+        assert_eq!(spec.url, "https://gitlab.com/org/repo.git");
+        assert_eq!(spec.committish, None);
+
+        let spec = GitSpec::parse("git+ssh://git@host/repo.git#").unwrap();
+        assert_eq!(spec.url, "ssh://git@host/repo.git");
+        assert_eq!(spec.committish, None);
+
+        assert!(GitSpec::parse("^1.0.0").is_none());
+        assert!(GitSpec::parse("@scope/name").is_none());
+        assert!(GitSpec::parse("./local/dir").is_none());
+    }
+
+    #[test]
+    fn finds_commits() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        let first = commit(&repo, &[("package.json", r#"{"version":"1.0.0"}"#)]);
+        tag(&repo, "v1.0.0", first);
+        let second = commit(&repo, &[("package.json", r#"{"version":"1.2.0"}"#)]);
+        tag(&repo, "1.2.0", second);
+        let third = commit(&repo, &[("package.json", r#"{"version":"2.0.0"}"#)]);
+        tag(&repo, "v2.0.0", third);
+        tag(&repo, "not-a-version", third);
+
+        let find = |committish: Option<&str>| find_commit(&repo, committish).unwrap();
+
+        assert_eq!(find(None), Some(third));
+        assert_eq!(find(Some("v1.0.0")), Some(first));
+        assert_eq!(find(Some(&second.to_string())), Some(second));
+        assert_eq!(find(Some("semver:^1")), Some(second));
+        assert_eq!(find(Some("semver:~1.0.0")), Some(first));
+        assert_eq!(find(Some("semver:>=2")), Some(third));
+        assert_eq!(find(Some("semver:^3")), None);
+        assert_eq!(find(Some("missing-branch")), None);
+    }
+}
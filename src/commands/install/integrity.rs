@@ -0,0 +1,178 @@
+use std::io::Read;
+
+use ssri::{Algorithm, Integrity, IntegrityOpts};
+
+use crate::{
+    package::metadata::MetadataVersion,
+    result::{NanaError, NanaResult, PackageError},
+};
+
+enum Expected {
+    Sri(Integrity),
+    Shasum(String),
+}
+
+/// Incrementally hashes a tarball and checks it against the `dist` hashes
+/// advertised by the registry. `integrity` (SRI) takes precedence over the
+/// legacy hex `shasum`, and tarballs with neither are refused.
+pub struct IntegrityVerifier {
+    key: String,
+    expected: Expected,
+    hasher: IntegrityOpts,
+}
+
+impl IntegrityVerifier {
+    pub fn new(meta_version: &MetadataVersion) -> NanaResult<Self> {
+        let (expected, algorithm) = match (&meta_version.dist.integrity, &meta_version.dist.shasum)
+        {
+            (Some(integrity), _) => {
+                let integrity: Integrity = integrity.parse()?;
+                let algorithm = integrity.pick_algorithm();
+                (Expected::Sri(integrity), algorithm)
+            }
+            (None, Some(shasum)) => (Expected::Shasum(shasum.to_lowercase()), Algorithm::Sha1),
+            (None, None) => {
+                return Err(NanaError::Package(PackageError::MissingIntegrity(
+                    meta_version.key(),
+                )))
+            }
+        };
+
+        Ok(Self {
+            key: meta_version.key(),
+            expected,
+            hasher: IntegrityOpts::new().algorithm(algorithm),
+        })
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.input(chunk);
+    }
+
+    pub fn finish(self) -> NanaResult<Integrity> {
+        let actual = self.hasher.result();
+
+        match self.expected {
+            Expected::Sri(expected) => {
+                if actual.matches(&expected).is_some() {
+                    Ok(actual)
+                } else {
+                    Err(mismatch(self.key, expected.to_string(), actual.to_string()))
+                }
+            }
+            Expected::Shasum(expected) => {
+                let (_, hex) = actual.to_hex();
+                if hex == expected {
+                    Ok(actual)
+                } else {
+                    Err(mismatch(
+                        self.key,
+                        format!("sha1 {}", expected),
+                        format!("sha1 {}", hex),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Hashes everything read through it, so a tarball can be verified while it
+/// is being unpacked.
+pub struct VerifyingReader<R> {
+    inner: R,
+    verifier: IntegrityVerifier,
+}
+
+impl<R: Read> VerifyingReader<R> {
+    pub fn new(inner: R, meta_version: &MetadataVersion) -> NanaResult<Self> {
+        Ok(Self {
+            inner,
+            verifier: IntegrityVerifier::new(meta_version)?,
+        })
+    }
+
+    /// Reads whatever was left unread, e.g. the padding after the end of a
+    /// tar archive, and checks the whole content.
+    pub fn finish(mut self) -> NanaResult<Integrity> {
+        std::io::copy(&mut self, &mut std::io::sink())?;
+
+        self.verifier.finish()
+    }
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.verifier.update(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+pub fn verify(meta_version: &MetadataVersion, data: &[u8]) -> NanaResult<Integrity> {
+    let mut verifier = IntegrityVerifier::new(meta_version)?;
+    verifier.update(data);
+    verifier.finish()
+}
+
+fn mismatch(key: String, expected: String, actual: String) -> NanaError {
+    NanaError::Package(PackageError::IntegrityMismatch(key, expected, actual))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::install::testing::TestPackage;
+
+    fn meta_version(shasum: Option<&str>, integrity: Option<&str>) -> MetadataVersion {
+        TestPackage::new("a", "1.0.0")
+            .shasum(shasum)
+            .integrity(integrity)
+            .build()
+    }
+
+    fn sha512(data: &[u8]) -> String {
+        IntegrityOpts::new()
+            .algorithm(Algorithm::Sha512)
+            .chain(data)
+            .result()
+            .to_string()
+    }
+
+    #[test]
+    fn accepts_matching_integrity() {
+        let meta_version = meta_version(None, Some(&sha512(b"tarball")));
+
+        assert!(verify(&meta_version, b"tarball").is_ok());
+    }
+
+    #[test]
+    fn rejects_integrity_mismatch() {
+        let meta_version = meta_version(None, Some(&sha512(b"tarball")));
+
+        assert!(matches!(
+            verify(&meta_version, b"tampered"),
+            Err(NanaError::Package(PackageError::IntegrityMismatch(key, _, _))) if key == "a@1.0.0"
+        ));
+    }
+
+    #[test]
+    fn checks_legacy_shasum() {
+        // sha1 of "tarball", in upper case as some registries send it
+        let meta_version = meta_version(Some("E10F6E70661D167EF514AB6E6D98607438C6A8C6"), None);
+
+        assert!(verify(&meta_version, b"tarball").is_ok());
+        assert!(matches!(
+            verify(&meta_version, b"tampered"),
+            Err(NanaError::Package(PackageError::IntegrityMismatch(..)))
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_hashes() {
+        assert!(matches!(
+            verify(&meta_version(None, None), b"tarball"),
+            Err(NanaError::Package(PackageError::MissingIntegrity(key))) if key == "a@1.0.0"
+        ));
+    }
+}
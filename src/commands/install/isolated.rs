@@ -0,0 +1,488 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use indexmap::IndexMap;
+use ssri::{Algorithm, IntegrityOpts};
+
+use crate::{
+    config::Config,
+    package::metadata::MetadataVersion,
+    result::{NanaError, NanaResult},
+};
+
+use super::{
+    bin,
+    layout::{self, Layout},
+    local,
+    modules::{self, NODE_MODULES_DIR},
+    specifier::Specifier,
+    Install,
+};
+
+/// Where every package is installed in the isolated layout.
+const VIRTUAL_STORE_DIR: &str = "node_modules/.nana";
+/// Length of the source hash distinguishing packages not from the registry.
+const SOURCE_ID_LENGTH: usize = 10;
+
+/// Isolated `node_modules`, like pnpm's: each package lives in
+/// `node_modules/.nana/<name>@<version>/node_modules/<name>`, next to
+/// symlinks to its own dependencies, so it cannot `require()` anything it
+/// does not declare. Only direct dependencies are linked at the top level.
+/// It is derived from the locked hoisted layout, which tells which version
+/// each dependency resolves to.
+#[derive(Debug, Default)]
+struct Isolated {
+    /// Packages whose files nana installs, by location.
+    packages: IndexMap<String, MetadataVersion>,
+    /// Symlink paths to the location of the package they point to.
+    links: IndexMap<String, (String, MetadataVersion)>,
+    /// Locations of the dependencies of each location.
+    edges: HashMap<String, Vec<String>>,
+    /// `node_modules` directories whose entries are all links of `links`.
+    managed_dirs: HashSet<String>,
+}
+
+impl Install {
+    /// Installs `dependencies` in the isolated layout, returning the packages
+    /// actually installed, dependencies first.
+    pub async fn install_isolated(
+        &self,
+        layout: &Layout,
+        dependencies: &[(String, MetadataVersion)],
+        direct: &[String],
+    ) -> NanaResult<Vec<(String, MetadataVersion)>> {
+        let isolated = Isolated::new(&self.config, layout, dependencies, direct)?;
+
+        let removed = isolated.prune()?;
+        bin::unlink_stale(&removed)?;
+
+        let missing: Vec<(String, MetadataVersion)> = isolated
+            .packages
+            .iter()
+            .filter(|(location, meta_version)| !modules::is_installed(location, meta_version))
+            .map(|(location, meta_version)| (location.clone(), meta_version.clone()))
+            .collect();
+
+        let installed = match missing.is_empty() {
+            true => vec![],
+            false => self.download(missing).await?,
+        };
+
+        for (path, (target, _)) in &isolated.links {
+            local::link(path, target)?;
+        }
+
+        let links: Vec<(String, MetadataVersion)> = isolated
+            .links
+            .iter()
+            .map(|(path, (_, meta_version))| (path.clone(), meta_version.clone()))
+            .collect();
+        bin::link_bins(&links, direct)?;
+
+        Ok(isolated.order(&installed))
+    }
+}
+
+/// Removes whatever is installed in the isolated layout but no longer part
+/// of `dependencies`.
+pub fn prune(
+    config: &Config,
+    layout: &Layout,
+    dependencies: &[(String, MetadataVersion)],
+    direct: &[String],
+) -> NanaResult<()> {
+    let removed = Isolated::new(config, layout, dependencies, direct)?.prune()?;
+
+    bin::unlink_stale(&removed)
+}
+
+impl Isolated {
+    fn new(
+        config: &Config,
+        layout: &Layout,
+        dependencies: &[(String, MetadataVersion)],
+        direct: &[String],
+    ) -> NanaResult<Self> {
+        let installable: HashMap<&String, &MetadataVersion> = dependencies
+            .iter()
+            .map(|(path, meta_version)| (path, meta_version))
+            .collect();
+
+        let mut isolated = Self::default();
+        isolated.managed_dirs.insert(NODE_MODULES_DIR.to_string());
+
+        for (path, meta_version) in dependencies {
+            let (location, dependencies_dir) = locate(config, meta_version);
+
+            if dependencies_dir.is_none() || is_workspace(meta_version) {
+                // Linked directories and workspaces are used in place
+            } else if !isolated.packages.contains_key(&location) {
+                isolated
+                    .packages
+                    .insert(location.clone(), meta_version.clone());
+            }
+
+            let dependencies_dir = match dependencies_dir {
+                Some(dir) => dir,
+                None => continue,
+            };
+
+            if is_workspace(meta_version) {
+                isolated.managed_dirs.insert(dependencies_dir.clone());
+            }
+
+            for (name, _, _) in meta_version.all_dependencies() {
+                let dependency = match layout::resolve(layout, path, &name)
+                    .and_then(|dependency| installable.get(&dependency))
+                {
+                    Some(dependency) => *dependency,
+                    None => continue,
+                };
+
+                let link = format!("{}/{}", dependencies_dir, name);
+                let (target, _) = locate(config, dependency);
+                // A package depending on itself already sits there
+                if link == location {
+                    continue;
+                }
+
+                isolated
+                    .edges
+                    .entry(location.clone())
+                    .or_default()
+                    .push(target.clone());
+                // Every copy of a package shares its dependency links, which
+                // only works as long as they all resolve the same versions
+                match isolated.links.get(&link) {
+                    Some((existing, _)) if *existing != target => {
+                        return Err(NanaError::Runtime(format!(
+                            "'{}' resolves '{}' to both '{}' and '{}'",
+                            meta_version.key(),
+                            name,
+                            existing,
+                            target
+                        )))
+                    }
+                    Some(_) => {}
+                    None => {
+                        isolated.links.insert(link, (target, dependency.clone()));
+                    }
+                }
+            }
+        }
+
+        for path in direct {
+            if let Some(meta_version) = installable.get(path) {
+                let (target, _) = locate(config, meta_version);
+                isolated
+                    .links
+                    .insert(path.clone(), (target, (*meta_version).clone()));
+            }
+        }
+
+        Ok(isolated)
+    }
+
+    /// Removes the packages and links that are no longer part of the layout,
+    /// returning the removed paths.
+    fn prune(&self) -> NanaResult<Vec<String>> {
+        let mut removed = vec![];
+
+        let expected: HashSet<String> = self
+            .packages
+            .keys()
+            .filter_map(|location| store_dir(location))
+            .collect();
+
+        if let Ok(entries) = std::fs::read_dir(VIRTUAL_STORE_DIR) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let path = format!(
+                    "{}/{}",
+                    VIRTUAL_STORE_DIR,
+                    entry.file_name().to_string_lossy()
+                );
+
+                if !expected.contains(&path) {
+                    std::fs::remove_dir_all(&path)?;
+                }
+            }
+        }
+
+        for dir in &self.managed_dirs {
+            for path in entries(dir) {
+                if self.links.contains_key(&path) {
+                    continue;
+                }
+
+                let path_ref = Path::new(&path);
+                match path_ref.is_symlink() {
+                    true => local::remove_link(path_ref)?,
+                    false => std::fs::remove_dir_all(path_ref)?,
+                }
+                removed.push(path);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// `installed` packages, each one after the packages it depends on.
+    /// Dependency cycles are broken arbitrarily.
+    fn order(&self, installed: &[(String, MetadataVersion)]) -> Vec<(String, MetadataVersion)> {
+        let installed: HashSet<&String> = installed.iter().map(|(location, _)| location).collect();
+
+        let mut visited = HashSet::new();
+        let mut order = vec![];
+        for location in self.packages.keys() {
+            self.visit(location, &mut visited, &mut order);
+        }
+
+        order
+            .into_iter()
+            .filter(|location| installed.contains(location))
+            .map(|location| (location.clone(), self.packages[&location].clone()))
+            .collect()
+    }
+
+    fn visit(&self, location: &str, visited: &mut HashSet<String>, order: &mut Vec<String>) {
+        if !visited.insert(location.to_string()) {
+            return;
+        }
+
+        for dependency in self.edges.get(location).into_iter().flatten() {
+            self.visit(dependency, visited, order);
+        }
+
+        if self.packages.contains_key(location) {
+            order.push(location.to_string());
+        }
+    }
+}
+
+/// Whether an isolated layout is currently installed.
+pub fn is_installed() -> bool {
+    Path::new(VIRTUAL_STORE_DIR).exists()
+}
+
+/// Where the files of a package live, and the `node_modules` its own
+/// dependencies are linked into. `link:` dependencies are left alone, and
+/// workspaces get their dependencies in their own `node_modules`. Packages
+/// not from the registry are told apart from the registry ones, and from
+/// each other, by their source: `<name>@<version>+<source id>`.
+fn locate(config: &Config, meta_version: &MetadataVersion) -> (String, Option<String>) {
+    match Specifier::parse(&meta_version.dist.tarball) {
+        Specifier::Link(target) => (target, None),
+        Specifier::Workspace(target) => {
+            let dependencies_dir = format!("{}/{}", target, NODE_MODULES_DIR);
+            (target, Some(dependencies_dir))
+        }
+        _ => {
+            let mut key = format!(
+                "{}@{}",
+                meta_version.name.replace('/', "+"),
+                meta_version.version
+            );
+            if let Some(id) = source_id(config, meta_version) {
+                key = format!("{}+{}", key, id);
+            }
+
+            let dir = format!("{}/{}/{}", VIRTUAL_STORE_DIR, key, NODE_MODULES_DIR);
+            (format!("{}/{}", dir, meta_version.name), Some(dir))
+        }
+    }
+}
+
+/// Short id of where a package not from the registry comes from: its commit
+/// for git, its integrity for tarballs and its path for directories.
+fn source_id(config: &Config, meta_version: &MetadataVersion) -> Option<String> {
+    let source = match Specifier::parse(&meta_version.dist.tarball) {
+        Specifier::Git(spec) => return spec.committish.map(|commit| short(&commit)),
+        Specifier::Tarball(url) if url.starts_with(&config.registry(&meta_version.name)) => {
+            return None
+        }
+        Specifier::Tarball(_) | Specifier::LocalTarball(_) => meta_version
+            .dist
+            .integrity
+            .clone()
+            .unwrap_or_else(|| meta_version.dist.tarball.clone()),
+        Specifier::Directory(path) => path,
+        _ => return None,
+    };
+
+    let (_, hex) = IntegrityOpts::new()
+        .algorithm(Algorithm::Sha512)
+        .chain(source.as_bytes())
+        .result()
+        .to_hex();
+
+    Some(short(&hex))
+}
+
+fn short(id: &str) -> String {
+    id.chars().take(SOURCE_ID_LENGTH).collect()
+}
+
+fn is_workspace(meta_version: &MetadataVersion) -> bool {
+    matches!(
+        Specifier::parse(&meta_version.dist.tarball),
+        Specifier::Workspace(_)
+    )
+}
+
+/// `node_modules/.nana/<name>@<version>` directory of a package location.
+fn store_dir(location: &str) -> Option<String> {
+    let rest = location
+        .strip_prefix(VIRTUAL_STORE_DIR)?
+        .trim_start_matches('/');
+    let dir = rest.split('/').next()?;
+
+    Some(format!("{}/{}", VIRTUAL_STORE_DIR, dir))
+}
+
+/// Package paths directly inside the `node_modules` directory `dir`,
+/// `@scope` ones included, skipping `.bin` and `.nana`.
+fn entries(dir: &str) -> Vec<String> {
+    let mut result = vec![];
+
+    let list = |dir: &str| -> Vec<String> {
+        match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| !name.starts_with('.'))
+                .collect(),
+            Err(_) => vec![],
+        }
+    };
+
+    for name in list(dir) {
+        let path = format!("{}/{}", dir, name);
+
+        match name.starts_with('@') && !Path::new(&path).is_symlink() {
+            true => result.extend(list(&path).iter().map(|n| format!("{}/{}", path, n))),
+            false => result.push(path),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::install::{layout::Resolution, testing::TestPackage},
+        package::metadata::DependencyKind,
+    };
+
+    fn package(name: &str, version: &str, tarball: &str) -> MetadataVersion {
+        TestPackage::new(name, version).tarball(tarball).build()
+    }
+
+    #[test]
+    fn locates_by_source() {
+        let mut config = Config::default();
+        config.set("registry", "https://r.example/");
+
+        let registry = package("a", "1.0.0", "https://r.example/a/-/a-1.0.0.tgz");
+        assert_eq!(
+            locate(&config, &registry).0,
+            "node_modules/.nana/a@1.0.0/node_modules/a"
+        );
+
+        let git = package("a", "1.0.0", "git+https://g.example/a.git#0123456789abcdef");
+        assert_eq!(
+            locate(&config, &git).0,
+            "node_modules/.nana/a@1.0.0+0123456789/node_modules/a"
+        );
+
+        let first = locate(&config, &package("a", "1.0.0", "file:../one")).0;
+        let second = locate(&config, &package("a", "1.0.0", "file:../two")).0;
+        assert_ne!(first, second);
+        assert_ne!(first, locate(&config, &registry).0);
+
+        // The same version from another host is not the registry's one
+        let remote = package("a", "1.0.0", "https://cdn.example/a-1.0.0.tgz");
+        assert_ne!(locate(&config, &remote).0, locate(&config, &registry).0);
+    }
+
+    /// Hoisted layout of the project requiring `a@^2`, `b@^1`, `x@^1` (which
+    /// requires `a@^1` and `b@^2`) and `y@^1` (which requires `a@^1`), so
+    /// both `x` and `y` get their own copy of `a@1`. `b@*` resolves to `b@2`.
+    fn layout(a: TestPackage) -> Layout {
+        let mut resolution = Resolution::default();
+        for (range, meta_version) in [
+            ("^1", a),
+            ("^2", TestPackage::new("a", "2.0.0")),
+            ("^1", TestPackage::new("b", "1.0.0")),
+            ("^2", TestPackage::new("b", "2.0.0")),
+            ("*", TestPackage::new("b", "2.0.0")),
+            (
+                "^1",
+                TestPackage::new("x", "1.0.0")
+                    .dependency("a", "^1")
+                    .dependency("b", "^2"),
+            ),
+            ("^1", TestPackage::new("y", "1.0.0").dependency("a", "^1")),
+        ] {
+            let meta_version = meta_version.build();
+            resolution.resolved.insert(
+                format!("{}@{}", meta_version.name, range),
+                meta_version.key(),
+            );
+            resolution.packages.insert(meta_version.key(), meta_version);
+        }
+
+        let roots: Vec<(String, String, DependencyKind)> =
+            [("a", "^2"), ("b", "^1"), ("x", "^1"), ("y", "^1")]
+                .into_iter()
+                .map(|(name, range)| {
+                    (
+                        name.to_string(),
+                        range.to_string(),
+                        DependencyKind::Production,
+                    )
+                })
+                .collect();
+
+        layout::hoist(&roots, &resolution).unwrap()
+    }
+
+    fn isolated(layout: &Layout) -> NanaResult<Isolated> {
+        let dependencies: Vec<(String, MetadataVersion)> = layout
+            .iter()
+            .map(|(path, meta_version)| (path.clone(), meta_version.clone()))
+            .collect();
+
+        Isolated::new(&Config::default(), layout, &dependencies, &[])
+    }
+
+    #[test]
+    fn shares_copies_of_a_package() {
+        // Hoisting nests `b@2` under the copy of `a@1` that would see `b@1`
+        let layout = layout(TestPackage::new("a", "1.0.0").dependency("b", "*"));
+        assert!(layout.contains_key("node_modules/x/node_modules/a"));
+        assert!(layout.contains_key("node_modules/y/node_modules/a/node_modules/b"));
+
+        let isolated = isolated(&layout).unwrap();
+        assert!(isolated
+            .packages
+            .contains_key("node_modules/.nana/a@1.0.0/node_modules/a"));
+        assert_eq!(
+            isolated.links["node_modules/.nana/a@1.0.0/node_modules/b"].0,
+            "node_modules/.nana/b@2.0.0/node_modules/b"
+        );
+    }
+
+    #[test]
+    fn rejects_copies_resolving_different_dependencies() {
+        // The optional `b@^3` could not be resolved, so each copy of `a@1`
+        // falls back to whichever `b` it sees: `b@2` under `x`, `b@1` under `y`
+        let layout = layout(TestPackage::new("a", "1.0.0").optional_dependency("b", "^3"));
+        assert!(!layout.contains_key("node_modules/x/node_modules/a/node_modules/b"));
+
+        assert!(isolated(&layout).is_err());
+    }
+}
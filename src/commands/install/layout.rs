@@ -0,0 +1,327 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use indexmap::IndexMap;
+
+use crate::{
+    package::metadata::{DependencyKind, MetadataVersion},
+    result::{NanaError, NanaResult},
+};
+
+use super::modules::NODE_MODULES_DIR;
+
+/// Install path (e.g. `node_modules/a/node_modules/b`) to the package living there.
+pub type Layout = IndexMap<String, MetadataVersion>;
+
+/// Output of the resolver: every picked package version, plus which version
+/// was picked for each requested `name@range`.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub packages: HashMap<String, MetadataVersion>,
+    pub resolved: HashMap<String, String>,
+}
+
+impl Resolution {
+    pub fn get(&self, name: &str, version_range: &str) -> Option<&MetadataVersion> {
+        self.resolved
+            .get(&format!("{}@{}", name, version_range))
+            .and_then(|key| self.packages.get(key))
+    }
+}
+
+/// Hoists every package as close to the project root as possible. A package is
+/// only nested under its dependent when a different version of the same name is
+/// already visible from there, mirroring how `require()` walks up the tree.
+pub fn hoist(
+    roots: &[(String, String, DependencyKind)],
+    resolution: &Resolution,
+) -> NanaResult<Layout> {
+    let mut layout = Layout::new();
+    let mut queue = VecDeque::new();
+
+    for (name, version_range, _) in roots {
+        if let Some(meta_version) = resolution.get(name, version_range) {
+            let path = child_path("", name);
+            layout.insert(path.clone(), meta_version.clone());
+            queue.push_back(path);
+        }
+    }
+
+    while let Some(parent) = queue.pop_front() {
+        for (name, version_range, _) in layout[&parent].all_dependencies() {
+            let meta_version = match resolution.get(&name, &version_range) {
+                Some(meta_version) => meta_version,
+                None => continue,
+            };
+
+            if let Some(path) = placement(&layout, &parent, &name, meta_version)? {
+                layout.insert(path.clone(), meta_version.clone());
+                queue.push_back(path);
+            }
+        }
+    }
+
+    assign_kinds(&mut layout, roots);
+    layout.sort_keys();
+
+    Ok(layout)
+}
+
+/// Marks every package with the strongest kind it is reachable from:
+/// production first, then optional, and development for whatever is left.
+/// An edge never makes a package stronger than itself, so optional edges of
+/// production packages are optional, and so on.
+pub fn assign_kinds(layout: &mut Layout, roots: &[(String, String, DependencyKind)]) {
+    let mut assigned: HashMap<String, DependencyKind> = HashMap::new();
+    let mut weaker_edges: Vec<(DependencyKind, String)> = vec![];
+
+    for kind in DependencyKind::all() {
+        let mut queue: VecDeque<String> = roots
+            .iter()
+            .filter(|(_, _, k)| *k == kind)
+            .map(|(name, _, _)| child_path("", name))
+            .collect();
+
+        queue.extend(
+            weaker_edges
+                .iter()
+                .filter(|(k, _)| *k == kind)
+                .map(|(_, path)| path.clone()),
+        );
+
+        while let Some(path) = queue.pop_front() {
+            if assigned.contains_key(&path) || !layout.contains_key(&path) {
+                continue;
+            }
+
+            assigned.insert(path.clone(), kind);
+
+            for (name, _, edge_kind) in layout[&path].all_dependencies() {
+                if let Some(dependency) = resolve(layout, &path, &name) {
+                    match kind.max(edge_kind) {
+                        k if k == kind => queue.push_back(dependency),
+                        k => weaker_edges.push((k, dependency)),
+                    }
+                }
+            }
+        }
+    }
+
+    for (path, meta_version) in layout.iter_mut() {
+        meta_version.kind = assigned
+            .get(path)
+            .copied()
+            .unwrap_or(DependencyKind::Development);
+    }
+}
+
+/// Install paths ordered so that every package comes after the packages it
+/// depends on. Dependency cycles are broken arbitrarily.
+pub fn dependency_order(layout: &Layout) -> Vec<String> {
+    let mut visited = HashSet::new();
+    let mut order = vec![];
+
+    for path in layout.keys() {
+        visit(layout, path, &mut visited, &mut order);
+    }
+
+    order
+}
+
+fn visit(layout: &Layout, path: &str, visited: &mut HashSet<String>, order: &mut Vec<String>) {
+    if !visited.insert(path.to_string()) {
+        return;
+    }
+
+    for (name, _, _) in layout[path].all_dependencies() {
+        if let Some(dependency) = resolve(layout, path, &name) {
+            visit(layout, &dependency, visited, order);
+        }
+    }
+
+    order.push(path.to_string());
+}
+
+/// Finds the install path `require(name)` resolves to from the package at
+/// `from`, walking up the tree like Node.js does.
+pub fn resolve(layout: &Layout, from: &str, name: &str) -> Option<String> {
+    let mut level = Some(from.to_string());
+
+    while let Some(current) = level {
+        let path = child_path(&current, name);
+        if layout.contains_key(&path) {
+            return Some(path);
+        }
+
+        level = parent_path(&current);
+    }
+
+    None
+}
+
+/// Returns where `name` has to be placed to be visible from `parent`, or
+/// `None` when the same version is already reachable.
+fn placement(
+    layout: &Layout,
+    parent: &str,
+    name: &str,
+    meta_version: &MetadataVersion,
+) -> NanaResult<Option<String>> {
+    let mut level = Some(parent.to_string());
+
+    while let Some(current) = level {
+        if let Some(installed) = layout.get(&child_path(&current, name)) {
+            if installed.key() == meta_version.key() {
+                return Ok(None);
+            }
+
+            let nested = child_path(parent, name);
+            if layout.contains_key(&nested) {
+                return Err(NanaError::Runtime(format!(
+                    "Unable to place '{}' under '{}'",
+                    meta_version.key(),
+                    parent
+                )));
+            }
+
+            return Ok(Some(nested));
+        }
+
+        level = parent_path(&current);
+    }
+
+    Ok(Some(child_path("", name)))
+}
+
+/// `node_modules` path of `name` when installed under the package at `parent`
+/// (an empty `parent` being the project root).
+pub fn child_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        format!("{}/{}", NODE_MODULES_DIR, name)
+    } else {
+        format!("{}/{}/{}", parent, NODE_MODULES_DIR, name)
+    }
+}
+
+/// Install path of the package containing `path`, `Some("")` for top-level
+/// packages and `None` for the project root itself.
+pub fn parent_path(path: &str) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+
+    match path.rfind(&format!("/{}/", NODE_MODULES_DIR)) {
+        Some(index) => Some(path[..index].to_string()),
+        None => Some(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::install::testing::TestPackage;
+
+    /// `a@1 -> (b@1, c@^1)`, `b@1 -> c@^2`, `c@2 -> a@^1` and the root
+    /// requiring `a@^1` and `c@^2`.
+    fn resolution() -> Resolution {
+        let mut resolution = Resolution::default();
+
+        for (range, meta_version) in [
+            (
+                "^1",
+                TestPackage::new("a", "1.0.0")
+                    .dependency("b", "1")
+                    .dependency("c", "^1"),
+            ),
+            ("1", TestPackage::new("b", "1.0.0").dependency("c", "^2")),
+            ("^1", TestPackage::new("c", "1.0.0")),
+            ("^2", TestPackage::new("c", "2.0.0").dependency("a", "^1")),
+        ] {
+            let meta_version = meta_version.build();
+            resolution.resolved.insert(
+                format!("{}@{}", meta_version.name, range),
+                meta_version.key(),
+            );
+            resolution.packages.insert(meta_version.key(), meta_version);
+        }
+
+        resolution
+    }
+
+    fn roots() -> Vec<(String, String, DependencyKind)> {
+        vec![
+            (
+                "a".to_string(),
+                "^1".to_string(),
+                DependencyKind::Production,
+            ),
+            (
+                "c".to_string(),
+                "^2".to_string(),
+                DependencyKind::Development,
+            ),
+        ]
+    }
+
+    #[test]
+    fn hoists_unless_another_version_is_visible() {
+        let layout = hoist(&roots(), &resolution()).unwrap();
+
+        let installed: Vec<(&str, String)> = layout
+            .iter()
+            .map(|(path, meta_version)| (path.as_str(), meta_version.key()))
+            .collect();
+
+        assert_eq!(
+            installed,
+            vec![
+                ("node_modules/a", "a@1.0.0".to_string()),
+                ("node_modules/a/node_modules/c", "c@1.0.0".to_string()),
+                ("node_modules/b", "b@1.0.0".to_string()),
+                ("node_modules/c", "c@2.0.0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn assigns_strongest_kind() {
+        let layout = hoist(&roots(), &resolution()).unwrap();
+
+        // `c@2` is a dev dependency of the project, but required by `b`
+        assert!(layout
+            .values()
+            .all(|meta_version| meta_version.kind == DependencyKind::Production));
+    }
+
+    #[test]
+    fn orders_dependencies_first() {
+        let layout = hoist(&roots(), &resolution()).unwrap();
+        let order = dependency_order(&layout);
+        let position = |path: &str| order.iter().position(|p| p == path).unwrap();
+
+        assert_eq!(order.len(), layout.len());
+        assert!(position("node_modules/a/node_modules/c") < position("node_modules/a"));
+        assert!(position("node_modules/c") < position("node_modules/b"));
+        assert!(position("node_modules/b") < position("node_modules/a"));
+    }
+
+    #[test]
+    fn resolves_like_node() {
+        let layout = hoist(&roots(), &resolution()).unwrap();
+
+        assert_eq!(
+            resolve(&layout, "node_modules/a", "c").as_deref(),
+            Some("node_modules/a/node_modules/c")
+        );
+        assert_eq!(
+            resolve(&layout, "node_modules/b", "c").as_deref(),
+            Some("node_modules/c")
+        );
+        assert_eq!(resolve(&layout, "node_modules/a/node_modules/c", "d"), None);
+        assert_eq!(
+            parent_path("node_modules/a/node_modules/@s/x").as_deref(),
+            Some("node_modules/a")
+        );
+        assert_eq!(parent_path("node_modules/a").as_deref(), Some(""));
+        assert_eq!(parent_path(""), None);
+    }
+}
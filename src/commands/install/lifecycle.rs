@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use console::style;
+
+use crate::{
+    config::Config,
+    package::{
+        manifest::Manifest,
+        metadata::{DependencyKind, MetadataVersion},
+    },
+    result::{NanaError, NanaResult, PackageError},
+    script,
+};
+
+use super::Install;
+
+/// Scripts run for every freshly installed dependency, in order.
+const INSTALL_EVENTS: [&str; 3] = ["preinstall", "install", "postinstall"];
+
+/// Scripts run for the project itself once its dependencies are installed.
+pub const ROOT_POST_INSTALL_EVENTS: [&str; 3] = ["install", "postinstall", "prepare"];
+
+const NODE_GYP_FILE: &str = "binding.gyp";
+const NODE_GYP_REBUILD: &str = "node-gyp rebuild";
+
+impl Install {
+    /// Runs the install scripts of `installed` packages, given with their
+    /// dependencies first. Failures of optional packages only produce a
+    /// warning, and the packages are returned so they can be removed.
+    pub async fn run_lifecycle_scripts(
+        &self,
+        installed: &[(String, MetadataVersion)],
+    ) -> NanaResult<Vec<MetadataVersion>> {
+        let packages = installed
+            .iter()
+            .filter_map(|(path, meta_version)| {
+                let manifest = Manifest::from_dir(Path::new(path)).ok()?;
+                has_install_scripts(&manifest, path).then_some((path, meta_version, manifest))
+            })
+            .collect::<Vec<(&String, &MetadataVersion, Manifest)>>();
+
+        if packages.is_empty() {
+            return Ok(vec![]);
+        }
+
+        self.state().progress_reset("Running install scripts").await;
+        self.state()
+            .progress_set_length(packages.len() as u64)
+            .await;
+
+        let mut failed = vec![];
+        for (path, meta_version, manifest) in packages {
+            let result = INSTALL_EVENTS
+                .iter()
+                .try_for_each(|event| run_captured(&manifest, &self.config, path, event));
+
+            match result {
+                Err(error) if meta_version.kind == DependencyKind::Optional => {
+                    self.state()
+                        .warn(format!(
+                            "Install scripts of optional dependency '{}' failed, removing it: {}",
+                            meta_version.key(),
+                            error
+                        ))
+                        .await;
+                    failed.push(meta_version.clone());
+                }
+                result => result?,
+            }
+
+            self.state().progress_increment(1).await;
+        }
+
+        self.state().progress_finish().await;
+
+        Ok(failed)
+    }
+}
+
+/// Runs the project's own `events` scripts, streaming their output.
+pub fn run_root_scripts(config: &Config, events: &[&str]) -> NanaResult<()> {
+    let manifest = Manifest::from_local_package()?;
+
+    for event in events {
+        if let Some(cmd) = manifest.script(event) {
+            println!("{} {}", style(format!("> {}", event)).bold(), cmd);
+
+            let status = script::command(cmd, Path::new("."))?
+                .envs(script::environment(&manifest, config, event, cmd)?)
+                .status()?;
+
+            // The output was already streamed, only the status is left
+            if !status.success() {
+                return Err(NanaError::Package(PackageError::ScriptFailed(
+                    event.to_string(),
+                    script::exit_code(&status),
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn has_install_scripts(manifest: &Manifest, path: &str) -> bool {
+    INSTALL_EVENTS
+        .iter()
+        .any(|event| event_script(manifest, path, event).is_some())
+}
+
+/// Like npm, packages shipping a `binding.gyp` without their own `install`
+/// or `preinstall` script are built with `node-gyp rebuild`.
+fn event_script(manifest: &Manifest, path: &str, event: &str) -> Option<String> {
+    match manifest.script(event) {
+        Some(cmd) => Some(cmd.to_string()),
+        None if event == "install"
+            && manifest.script("preinstall").is_none()
+            && Path::new(path).join(NODE_GYP_FILE).exists() =>
+        {
+            Some(NODE_GYP_REBUILD.to_string())
+        }
+        None => None,
+    }
+}
+
+fn run_captured(manifest: &Manifest, config: &Config, path: &str, event: &str) -> NanaResult<()> {
+    let cmd = match event_script(manifest, path, event) {
+        Some(cmd) => cmd,
+        None => return Ok(()),
+    };
+
+    let output = script::command(&cmd, Path::new(path))?
+        .envs(script::environment(manifest, config, event, &cmd)?)
+        .output()?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(NanaError::Package(PackageError::LifecycleFailed(
+        manifest.name().unwrap_or(path).to_string(),
+        event.to_string(),
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+    )))
+}
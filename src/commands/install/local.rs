@@ -0,0 +1,120 @@
+use std::path::{Component, Path, PathBuf};
+
+use bytes::Bytes;
+
+use crate::{
+    package::{
+        metadata::{Dist, MetadataVersion},
+        PACKAGE_NAME,
+    },
+    result::NanaResult,
+};
+
+use super::{
+    packlist,
+    specifier::{FILE_PREFIX, LINK_PREFIX},
+};
+
+/// Resolves a directory copied into `node_modules`. Its dependencies are
+/// installed like any other package's, its own local ones being relative to
+/// it.
+pub fn resolve_directory(name: &str, path: &str) -> NanaResult<MetadataVersion> {
+    let content = std::fs::read(Path::new(path).join(PACKAGE_NAME))?;
+    let dist = Dist {
+        tarball: format!("{}{}", FILE_PREFIX, path),
+        shasum: None,
+        integrity: None,
+    };
+
+    let mut meta_version = MetadataVersion::from_manifest(name, &content, dist)?;
+    meta_version.rebase_dependencies(path);
+
+    Ok(meta_version)
+}
+
+/// Resolves a directory symlinked into `node_modules`. It is used as is, so
+/// its dependencies are left to the linked project.
+pub fn resolve_link(name: &str, path: &str) -> NanaResult<MetadataVersion> {
+    let content = std::fs::read(Path::new(path).join(PACKAGE_NAME)).unwrap_or(b"{}".to_vec());
+    let dist = Dist {
+        tarball: format!("{}{}", LINK_PREFIX, path),
+        shasum: None,
+        integrity: None,
+    };
+
+    let mut meta_version = MetadataVersion::from_manifest(name, &content, dist)?;
+    meta_version.dependencies = None;
+    meta_version.optional_dependencies = None;
+
+    Ok(meta_version)
+}
+
+/// Packs the publishable files of a directory, like `npm pack` would.
+pub fn pack_directory(path: &str) -> NanaResult<Bytes> {
+    packlist::pack(Path::new(path))
+}
+
+/// Points the install path `path` at `target`, a path relative to the
+/// project root. The link is relative too, so the project can move.
+pub fn link(path: &str, target: &str) -> NanaResult<()> {
+    let source = Path::new(path);
+    let destination = link_destination(path, target);
+
+    match source.symlink_metadata() {
+        Ok(metadata) if metadata.is_symlink() => {
+            if std::fs::read_link(source)? == destination {
+                return Ok(());
+            }
+            remove_link(source)?
+        }
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(source)?,
+        Ok(_) => std::fs::remove_file(source)?,
+        Err(_) => {}
+    }
+
+    if let Some(parent) = source.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    symlink_dir(&destination, source)
+}
+
+/// Whether the install path `path` is already linked to `target`.
+pub fn is_linked(path: &str, target: &str) -> bool {
+    std::fs::read_link(path).is_ok_and(|destination| destination == link_destination(path, target))
+}
+
+/// What the link at `path` points to for `target`, relative to the link.
+fn link_destination(path: &str, target: &str) -> PathBuf {
+    let source = Path::new(path);
+    match Path::new(target).is_absolute() {
+        true => PathBuf::from(target),
+        false => {
+            let depth = source.parent().map_or(0, |parent| {
+                parent
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .count()
+            });
+
+            std::iter::repeat_n(Path::new(".."), depth)
+                .collect::<PathBuf>()
+                .join(target)
+        }
+    }
+}
+
+/// Directory links are files on unix but directories on Windows.
+pub fn remove_link(path: &Path) -> NanaResult<()> {
+    Ok(std::fs::remove_file(path).or_else(|_| std::fs::remove_dir(path))?)
+}
+
+#[cfg(unix)]
+fn symlink_dir(destination: &Path, source: &Path) -> NanaResult<()> {
+    Ok(std::os::unix::fs::symlink(destination, source)?)
+}
+
+#[cfg(windows)]
+fn symlink_dir(destination: &Path, source: &Path) -> NanaResult<()> {
+    Ok(std::os::windows::fs::symlink_dir(destination, source)?)
+}
@@ -1,20 +1,33 @@
-use std::path::Path;
+use std::{collections::HashSet, path::Path};
 
 use indexmap::IndexMap;
 use semver_rs::{Range, Version};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    package::{metadata::MetadataVersion, Package},
+    package::{
+        metadata::{DependencyKind, MetadataVersion},
+        Package,
+    },
     result::{LockError, NanaError, NanaResult},
 };
 
+use super::{
+    layout::{assign_kinds, child_path, resolve, Layout},
+    specifier::Specifier,
+};
+
 const LOCK_NAME: &str = "nana.lock.yml";
 
+/// Locked packages keyed by their install path inside `node_modules`.
 type Dependencies = IndexMap<String, MetadataVersion>;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Lock {
+    /// Dependencies declared in `package.json` as of the last install, to
+    /// tell whether specifiers that are not semver ranges changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    specifiers: Option<IndexMap<String, String>>,
     dependencies: Option<Dependencies>,
     #[serde(skip)]
     is_dirty: bool,
@@ -23,6 +36,7 @@ pub struct Lock {
 impl Default for Lock {
     fn default() -> Self {
         Self {
+            specifiers: None,
             dependencies: None,
             is_dirty: true,
         }
@@ -52,6 +66,11 @@ impl Lock {
         Ok(())
     }
 
+    /// Whether the lock changed since it was loaded.
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty
+    }
+
     pub fn save_if_dirty(&mut self) -> NanaResult<()> {
         if !self.is_dirty {
             return Ok(());
@@ -61,69 +80,204 @@ impl Lock {
     }
 
     pub fn matches(&self, package: &Package) -> NanaResult<bool> {
-        if (self.dependencies.is_none() && package.has_dependencies())
-            || (self.dependencies.is_some() && !package.has_dependencies())
+        let has_dependencies = package.has_dependencies()?;
+        if (self.dependencies.is_none() && has_dependencies)
+            || (self.dependencies.is_some() && !has_dependencies)
         {
             return Ok(false);
         }
 
-        let deps = &package.dependencies();
+        for (name, version_range, _) in package.all_dependencies()? {
+            if !self.contains_matching_version(&name, &version_range)? {
+                return Ok(false);
+            }
+        }
+
+        // Workspaces are locked with their own dependencies, which have to
+        // be resolved again whenever they change
+        for workspace in package.workspaces()? {
+            let locked = self
+                .dependencies
+                .as_ref()
+                .and_then(|dependencies| dependencies.get(&child_path("", &workspace.name)));
 
-        Ok(deps
-            .iter()
-            .filter_map(|(name, version_range)| {
-                if self.contains_matching_version(name, version_range).unwrap() {
-                    Some(true)
-                } else {
-                    None
-                }
-            })
-            .count()
-            == deps.len())
+            if !locked.is_some_and(|locked| workspace.matches(locked)) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
     }
 
-    fn contains_matching_version(&self, name: &String, version_range: &str) -> NanaResult<bool> {
+    /// Direct dependencies are always installed at the top level, so only
+    /// `node_modules/<name>` has to be the requested package (the aliased one
+    /// for aliases) and satisfy the range. Other specifiers cannot be checked
+    /// offline, the locked version is kept until they change in
+    /// `package.json`.
+    fn contains_matching_version(&self, name: &str, version_range: &str) -> NanaResult<bool> {
         match &self.dependencies {
-            Some(dependencies) => {
-                let range = Range::new(version_range).parse()?;
-
-                Ok(dependencies
-                    .iter()
-                    .filter(|(_, meta_version)| meta_version.name.eq(name))
-                    .filter_map(|(_, meta_version)| {
-                        let version = Version::new(&meta_version.version).parse().unwrap();
-                        if range.test(&version) {
-                            Some(true)
-                        } else {
-                            None
-                        }
-                    })
-                    .count()
-                    > 0)
-            }
+            Some(dependencies) => match dependencies.get(&child_path("", name)) {
+                Some(meta_version) => match target(name, version_range) {
+                    (target, Specifier::Range(range)) => {
+                        let range = Range::new(&range).parse()?;
+                        Ok(meta_version.name == target
+                            && range.test(&Version::new(&meta_version.version).parse()?))
+                    }
+                    _ => Ok(self
+                        .specifiers
+                        .as_ref()
+                        .and_then(|specifiers| specifiers.get(name))
+                        .is_some_and(|specifier| specifier == version_range)),
+                },
+                None => Ok(false),
+            },
             None => Ok(false),
         }
     }
 
-    pub fn flat_dependencies(&self) -> Vec<MetadataVersion> {
+    pub fn flat_dependencies(&self) -> Vec<(String, MetadataVersion)> {
         match &self.dependencies {
-            Some(dependencies) => dependencies.iter().map(|(_, v)| v.clone()).collect(),
+            Some(dependencies) => dependencies
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
             None => vec![],
         }
     }
 
-    pub fn set_dependencies(&mut self, deps: &[(String, MetadataVersion)]) {
-        let mut result = Dependencies::new();
+    pub fn layout(&self) -> Layout {
+        self.dependencies.clone().unwrap_or_default()
+    }
+
+    /// Locked dependencies minus the kinds being omitted from this install.
+    pub fn installable_dependencies(
+        &self,
+        omit: &[DependencyKind],
+    ) -> Vec<(String, MetadataVersion)> {
+        self.flat_dependencies()
+            .into_iter()
+            .filter(|(_, meta_version)| !omit.contains(&meta_version.kind))
+            .collect()
+    }
+
+    /// Drops every locked package that is no longer reachable from the
+    /// dependencies declared in `package`, and refreshes the kind of the
+    /// remaining ones in case they moved between sections.
+    pub fn prune(&mut self, package: &Package) -> NanaResult<()> {
+        let roots = package.all_dependencies()?;
+        let specifiers: IndexMap<String, String> = roots
+            .iter()
+            .cloned()
+            .map(|(name, version_range, _)| (name, version_range))
+            .collect();
+
+        if self.specifiers.as_ref() != Some(&specifiers) {
+            self.specifiers = Some(specifiers);
+            self.is_dirty = true;
+        }
+
+        let dependencies = match &mut self.dependencies {
+            Some(dependencies) => dependencies,
+            None => return Ok(()),
+        };
 
-        deps.iter().for_each(|(name, version)| {
-            result.insert(name.clone(), version.clone());
+        let mut reachable = HashSet::new();
+        let mut queue: Vec<String> = roots
+            .iter()
+            .map(|(name, _, _)| child_path("", name))
+            .collect();
+
+        while let Some(path) = queue.pop() {
+            if !dependencies.contains_key(&path) || !reachable.insert(path.clone()) {
+                continue;
+            }
+
+            for (name, _, _) in dependencies[&path].all_dependencies() {
+                if let Some(dependency) = resolve(dependencies, &path, &name) {
+                    queue.push(dependency);
+                }
+            }
+        }
+
+        if reachable.len() != dependencies.len() {
+            dependencies.retain(|path, _| reachable.contains(path));
+            self.is_dirty = true;
+        }
+
+        let kinds: Vec<DependencyKind> = dependencies.values().map(|v| v.kind).collect();
+        assign_kinds(dependencies, &roots);
+
+        if !dependencies.values().map(|v| v.kind).eq(kinds) {
+            self.is_dirty = true;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the optional packages of `removed`, e.g. after their install
+    /// scripts failed. Their own dependencies are left for `prune`.
+    pub fn remove_optional(&mut self, removed: &[MetadataVersion]) {
+        let dependencies = match &mut self.dependencies {
+            Some(dependencies) => dependencies,
+            None => return,
+        };
+
+        let count = dependencies.len();
+        dependencies.retain(|_, meta_version| {
+            meta_version.kind != DependencyKind::Optional
+                || !removed.iter().any(|r| r.key() == meta_version.key())
         });
 
-        self.dependencies = Some(result);
+        if dependencies.len() != count {
+            self.is_dirty = true;
+        }
+    }
+
+    pub fn set_dependencies(&mut self, layout: Layout) {
+        self.dependencies = Some(layout);
         self.is_dirty = true;
     }
 }
 
+/// Finds a locked version of `name` satisfying `version_range`, if any.
+/// Workspaces are never picked, they are looked up by the resolver itself.
+pub fn find_satisfying(
+    locked: &[(String, MetadataVersion)],
+    name: &str,
+    version_range: &str,
+) -> Option<MetadataVersion> {
+    let (name, range) = match target(name, version_range) {
+        (name, Specifier::Range(range)) => (name, Range::new(&range).parse().ok()?),
+        _ => return None,
+    };
+
+    locked
+        .iter()
+        .map(|(_, meta_version)| meta_version)
+        .filter(|meta_version| {
+            meta_version.name.eq(&name)
+                && !matches!(
+                    Specifier::parse(&meta_version.dist.tarball),
+                    Specifier::Workspace(_)
+                )
+        })
+        .find(
+            |meta_version| match Version::new(&meta_version.version).parse() {
+                Ok(version) => range.test(&version),
+                Err(_) => false,
+            },
+        )
+        .cloned()
+}
+
+/// Name of the package actually requested by dependency `name`, and how.
+fn target(name: &str, version_range: &str) -> (String, Specifier) {
+    match Specifier::parse(version_range) {
+        Specifier::Alias(target, version_range) => (target, Specifier::parse(&version_range)),
+        specifier => (name.to_string(), specifier),
+    }
+}
+
 fn lock_in_dir() -> bool {
     Path::new(LOCK_NAME).exists()
 }
@@ -1,34 +1,121 @@
+mod bin;
 mod download;
-mod fetch;
+pub mod fetch;
+mod git;
+mod integrity;
+mod isolated;
+mod layout;
+mod lifecycle;
+mod local;
 mod lock;
 mod modules;
+mod packlist;
+pub mod specifier;
 mod state;
+mod store;
+mod tarball;
+#[cfg(test)]
+mod testing;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Arc,
+};
 
 use console::style;
+use semver_rs::{Range, Version};
+use tokio::sync::Semaphore;
 
 use crate::{
-    commands::install::fetch::fetch_metadata,
-    package::{metadata::MetadataVersion, Package},
-    result::NanaResult,
+    config::Config,
+    package::{
+        metadata::{DependencyKind, MetadataVersion},
+        Package,
+    },
+    result::{LockError, NanaError, NanaResult},
+};
+
+use self::{
+    fetch::Fetcher,
+    layout::{Layout, Resolution},
+    lock::Lock,
+    modules::NodeModules,
+    specifier::Specifier,
+    state::State,
 };
 
-use self::{lock::Lock, modules::NodeModules, state::State};
+/// Metadata requests in flight at once.
+const MAX_CONCURRENT_FETCHES: usize = 16;
 
+/// Commands processed by the resolver loop. The trailing `bool` marks
+/// optional dependencies, whose failures only produce a warning.
 #[derive(Debug)]
 pub enum InstallCommand {
-    FetchPackage(String, String),
-    AddPackage(String, String, MetadataVersion),
+    FetchPackage(String, String, bool),
+    AddPackage(String, String, Box<MetadataVersion>, bool),
+    SkipPackage(String, String),
+    /// A required dependency could not be fetched.
+    Fail(String, String, NanaError),
     Finish,
 }
 
+#[derive(Debug, Default, Clone)]
+pub struct InstallOptions {
+    /// Dependency kinds that are resolved and locked but not installed.
+    pub omit: Vec<DependencyKind>,
+    /// Skips lifecycle scripts of both dependencies and the project.
+    pub ignore_scripts: bool,
+    /// Fails instead of resolving again when `nana.lock.yml` is missing or
+    /// out of date, and never writes it.
+    pub frozen_lockfile: bool,
+    /// Deletes every `node_modules` before installing.
+    pub clean: bool,
+}
+
+/// How packages are laid out in `node_modules`, set with `node-linker`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Linker {
+    /// Every package as close to the root as possible, like npm.
+    Hoisted,
+    /// Each package only sees its own dependencies, like pnpm.
+    Isolated,
+}
+
+impl Linker {
+    fn from_config(config: &Config) -> NanaResult<Self> {
+        match config.get("node-linker") {
+            None | Some("hoisted") => Ok(Self::Hoisted),
+            Some("isolated") => Ok(Self::Isolated),
+            Some(other) => Err(NanaError::Runtime(format!(
+                "Unknown node-linker '{}', expected 'hoisted' or 'isolated'",
+                other
+            ))),
+        }
+    }
+}
+
 pub struct Install {
     state: State,
+    config: Config,
+    fetcher: Fetcher,
+    options: InstallOptions,
 }
 
 impl Install {
-    pub fn new() -> Self {
+    pub fn new(config: Config, options: InstallOptions) -> Self {
+        let fetcher = Fetcher::new(&config);
+
+        Self::with_fetcher(config, options, fetcher)
+    }
+
+    /// Installs with the metadata already fetched by `fetcher`.
+    pub fn with_fetcher(config: Config, options: InstallOptions, fetcher: Fetcher) -> Self {
         Self {
             state: State::new(),
+            config,
+            fetcher,
+            options,
         }
     }
 
@@ -42,12 +129,18 @@ impl Install {
         // 1.   Check if `package.json` is present
         // 2.   Load and validate `package.json`
         let package = Package::from_local_package()?;
+        let linker = Linker::from_config(&self.config)?;
+
+        // 2.1  Run the project's `preinstall` script
+        if !self.options.ignore_scripts {
+            lifecycle::run_root_scripts(&self.config, &["preinstall"])?;
+        }
 
         // 3.   Check if `nana.lock.yml` is present
         // 3.1  Load `nana.lock.yml`
-        let mut lock = match Lock::from_local_lock() {
-            Ok(lock) => lock,
-            Err(_) => Lock::default(),
+        let mut lock = match self.options.frozen_lockfile {
+            true => Lock::from_local_lock()?,
+            false => Lock::from_local_lock().unwrap_or_default(),
         };
 
         // 3.2  Calculate `package.json` integrity
@@ -55,90 +148,247 @@ impl Install {
         if lock.matches(&package)? {
             // 3.4  Load and use `nana.lock.yml` dependencies map
             lock.flat_dependencies();
+        } else if self.options.frozen_lockfile {
+            return Err(NanaError::Lock(LockError::OutOfDate));
         } else {
             // 4.   Calculate and load dependencies from `package.json`
-            let deps = self.resolve_dependencies(&package).await?;
-            lock.set_dependencies(&deps);
+            let resolution = self.resolve_dependencies(&package, &lock).await?;
+            lock.set_dependencies(layout::hoist(&package.all_dependencies()?, &resolution)?);
+        }
+
+        // 4.1  Drop locked packages no longer required by `package.json`
+        lock.prune(&package)?;
+        if self.options.frozen_lockfile && lock.is_dirty() {
+            return Err(NanaError::Lock(LockError::OutOfDate));
         }
 
         self.state().progress_finish().await;
         println!("Resolving dependencies: {}", style("OK").green());
 
-        // 5.   Load `node_modules`
-        let node_modules = NodeModules::from_local_dir()?;
+        // 5.   Start `node_modules` from scratch for clean installs, or when
+        //      switching to another linker
+        let is_switching = Path::new(modules::NODE_MODULES_DIR).exists()
+            && isolated::is_installed() != (linker == Linker::Isolated);
+        if self.options.clean || is_switching {
+            modules::remove_all(&package)?;
+        }
 
         // 5.1  Check if `node_modules` already contains required dependencies
-        if !node_modules.matches(&lock) {
-            // 6.   Download modules
-            self.download(&lock).await?;
-        }
+        // 6.   Download modules
+        // 6.1  Link executables into `node_modules/.bin`
+        let dependencies = lock.installable_dependencies(&self.options.omit);
+        let direct = package
+            .all_dependencies()?
+            .iter()
+            .map(|(name, _, _)| layout::child_path("", name))
+            .collect::<Vec<String>>();
+
+        let installed = match linker {
+            Linker::Hoisted => {
+                self.install_hoisted(&package, &dependencies, &direct)
+                    .await?
+            }
+            Linker::Isolated => {
+                self.install_isolated(&lock.layout(), &dependencies, &direct)
+                    .await?
+            }
+        };
 
         self.state().progress_finish().await;
         println!("Downloading dependencies: {}", style("OK").green());
 
-        lock.save_if_dirty()?;
+        if !self.options.frozen_lockfile {
+            lock.save_if_dirty()?;
+        }
+
+        // 7.   Run install scripts of new dependencies, then the project's own
+        if !self.options.ignore_scripts {
+            let failed = self.run_lifecycle_scripts(&installed).await?;
+
+            // 7.1  Like npm, drop optional dependencies whose scripts failed,
+            //      along with whatever only they required
+            if !failed.is_empty() {
+                lock.remove_optional(&failed);
+                lock.prune(&package)?;
+
+                let dependencies = lock.installable_dependencies(&self.options.omit);
+                match linker {
+                    Linker::Hoisted => prune_hoisted(&package, &dependencies)?,
+                    Linker::Isolated => {
+                        isolated::prune(&self.config, &lock.layout(), &dependencies, &direct)?
+                    }
+                };
+
+                if !self.options.frozen_lockfile {
+                    lock.save_if_dirty()?;
+                }
+            }
+
+            lifecycle::run_root_scripts(&self.config, &lifecycle::ROOT_POST_INSTALL_EVENTS)?;
+        }
 
         Ok(())
     }
 
+    /// Installs `dependencies` at their locked path, returning the packages
+    /// actually installed, dependencies first.
+    async fn install_hoisted(
+        &self,
+        package: &Package,
+        dependencies: &[(String, MetadataVersion)],
+        direct: &[String],
+    ) -> NanaResult<Vec<(String, MetadataVersion)>> {
+        let node_modules = scan_hoisted(package)?;
+
+        let removed = node_modules.prune(dependencies)?;
+        bin::unlink_stale(&removed)?;
+
+        let missing = node_modules.missing(dependencies);
+        let installed = match missing.is_empty() {
+            true => vec![],
+            false => self.download(missing).await?,
+        };
+
+        bin::link_bins(dependencies, direct)?;
+
+        let mut installed: HashMap<String, MetadataVersion> = installed.into_iter().collect();
+        let layout: Layout = dependencies.iter().cloned().collect();
+
+        Ok(layout::dependency_order(&layout)
+            .into_iter()
+            .filter_map(|path| installed.remove_entry(&path))
+            .collect())
+    }
+
+    /// Resolves every dependency of `package`, reusing versions already in
+    /// `lock` whenever they still satisfy the requested range.
     async fn resolve_dependencies(
         &mut self,
         package: &Package,
-    ) -> NanaResult<Vec<(String, MetadataVersion)>> {
+        lock: &Lock,
+    ) -> NanaResult<Resolution> {
         self.state().progress_reset("Resolving dependencies").await;
 
+        let locked = lock.flat_dependencies();
+        let workspaces: HashMap<String, MetadataVersion> = package
+            .workspaces()?
+            .iter()
+            .map(|workspace| (workspace.name.clone(), workspace.meta_version.clone()))
+            .collect();
+
         let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<InstallCommand>();
 
+        // Queued before the handler starts, so it cannot run out of work and
+        // finish while roots are still being sent
+        let roots = package.all_dependencies()?;
+        if roots.is_empty() {
+            tx.send(InstallCommand::Finish)?;
+        }
+
+        for (name, version_range, kind) in roots {
+            tx.send(InstallCommand::FetchPackage(
+                name,
+                version_range,
+                kind == DependencyKind::Optional,
+            ))?;
+        }
+
         let handler_tx = tx.clone();
         let state = self.state();
+        let fetcher = self.fetcher.clone();
+        let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_FETCHES));
         let handler = tokio::spawn(async move {
+            // Failures are gathered until resolution ends, so the reported
+            // one does not depend on which request completes first
+            let mut failures = BTreeMap::new();
+
             while let Some(cmd) = rx.recv().await {
                 let tx = handler_tx.clone();
                 match cmd {
-                    InstallCommand::FetchPackage(name, version_range) => {
+                    InstallCommand::FetchPackage(name, version_range, optional) => {
+                        state.package_started(&name, &version_range).await;
+                        state.progress_increment_length(1).await;
+
+                        if let Some(version) = find_workspace(&workspaces, &name, &version_range)
+                            .or_else(|| lock::find_satisfying(&locked, &name, &version_range))
+                        {
+                            tx.send(InstallCommand::AddPackage(
+                                name,
+                                version_range,
+                                Box::new(version),
+                                optional,
+                            ))?;
+                            continue;
+                        }
+
+                        // Fetched concurrently, the metadata of each name only once
+                        let fetcher = fetcher.clone();
+                        let state = state.clone();
+                        let permits = permits.clone();
+                        tokio::spawn(async move {
+                            let _permit = permits.acquire_owned().await?;
+                            let error = match fetcher
+                                .fetch_metadata(&name, &version_range, optional, tx.clone())
+                                .await
+                            {
+                                Ok(_) => return NanaResult::Ok(()),
+                                Err(error) => error,
+                            };
+
+                            if !optional {
+                                tx.send(InstallCommand::Fail(name, version_range, error))?;
+                                return Ok(());
+                            }
+
+                            state
+                                .warn(format!(
+                                    "Skipping optional dependency '{}@{}': {}",
+                                    name, version_range, error
+                                ))
+                                .await;
+
+                            tx.send(InstallCommand::SkipPackage(name, version_range))?;
+
+                            Ok(())
+                        });
+                    }
+                    InstallCommand::AddPackage(name, version_range, version, optional) => {
+                        let key = version.key();
                         state
                             .shared
                             .lock()
                             .await
-                            .dependencies_in_progress
-                            .insert(format!("{}@{}", name, version_range));
-
-                        state.progress_increment_length(1).await;
+                            .resolved
+                            .insert(format!("{}@{}", name, version_range), key.clone());
 
-                        fetch_metadata(&name, &version_range, tx).await.unwrap();
-                    }
-                    InstallCommand::AddPackage(name, version_range, version) => {
-                        let key = version.key();
                         if let std::collections::hash_map::Entry::Vacant(e) =
                             state.shared.lock().await.dependencies.entry(key)
                         {
-                            e.insert(version.clone());
+                            e.insert(*version.clone());
 
-                            if let Some(deps) = version.dependencies {
-                                for (name, version_range) in deps {
-                                    tx.send(InstallCommand::FetchPackage(name, version_range))
-                                        .unwrap();
-                                }
+                            for (name, version_range, kind) in version.all_dependencies() {
+                                tx.send(InstallCommand::FetchPackage(
+                                    name,
+                                    version_range,
+                                    optional || kind == DependencyKind::Optional,
+                                ))?;
                             }
                         }
 
-                        state
-                            .shared
-                            .lock()
-                            .await
-                            .dependencies_in_progress
-                            .remove(&format!("{}@{}", name, version_range));
-
-                        state.progress_increment(1).await;
+                        if state.package_done(&name, &version_range).await {
+                            tx.send(InstallCommand::Finish)?;
+                        }
+                    }
+                    InstallCommand::SkipPackage(name, version_range) => {
+                        if state.package_done(&name, &version_range).await {
+                            tx.send(InstallCommand::Finish)?;
+                        }
+                    }
+                    InstallCommand::Fail(name, version_range, error) => {
+                        failures.insert(format!("{}@{}", name, version_range), error);
 
-                        if state
-                            .shared
-                            .lock()
-                            .await
-                            .dependencies_in_progress
-                            .is_empty()
-                        {
-                            tx.send(InstallCommand::Finish).unwrap();
+                        if state.package_done(&name, &version_range).await {
+                            tx.send(InstallCommand::Finish)?;
                         }
                     }
                     InstallCommand::Finish => {
@@ -154,22 +404,61 @@ impl Install {
                     }
                 }
             }
+
+            match failures.into_values().next() {
+                Some(error) => Err(error),
+                None => NanaResult::Ok(()),
+            }
         });
 
-        for (name, version_range) in package.dependencies() {
-            tx.send(InstallCommand::FetchPackage(name, version_range))?;
-        }
+        handler.await??;
 
-        handler.await?;
+        let state = self.state();
+        let shared = state.shared.lock().await;
 
-        Ok(self
-            .state()
-            .shared
-            .lock()
-            .await
-            .dependencies
-            .iter()
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect::<Vec<(String, MetadataVersion)>>())
+        Ok(Resolution {
+            packages: shared.dependencies.clone(),
+            resolved: shared.resolved.clone(),
+        })
     }
 }
+
+/// Installed packages of the hoisted layout, looking into workspaces.
+fn scan_hoisted(package: &Package) -> NanaResult<NodeModules> {
+    let workspaces = package
+        .workspaces()?
+        .iter()
+        .map(|workspace| layout::child_path("", &workspace.name))
+        .collect::<Vec<String>>();
+
+    NodeModules::from_local_dir(&workspaces)
+}
+
+/// Removes whatever is installed in the hoisted layout but no longer part of
+/// `dependencies`.
+fn prune_hoisted(package: &Package, dependencies: &[(String, MetadataVersion)]) -> NanaResult<()> {
+    let removed = scan_hoisted(package)?.prune(dependencies)?;
+
+    bin::unlink_stale(&removed)
+}
+
+/// The workspace dependency `name@version_range` refers to: any `workspace:`
+/// specifier, or a semver range its version satisfies, like npm does.
+fn find_workspace(
+    workspaces: &HashMap<String, MetadataVersion>,
+    name: &str,
+    version_range: &str,
+) -> Option<MetadataVersion> {
+    let workspace = workspaces.get(name)?;
+
+    let is_match = match Specifier::parse(version_range) {
+        Specifier::Workspace(_) => true,
+        Specifier::Range(range) => {
+            let range = Range::new(&range).parse().ok()?;
+            range.test(&Version::new(&workspace.version).parse().ok()?)
+        }
+        _ => false,
+    };
+
+    is_match.then(|| workspace.clone())
+}
@@ -1,45 +1,202 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 
-use crate::result::NanaResult;
+use crate::{
+    package::{metadata::MetadataVersion, Package},
+    result::NanaResult,
+};
 
-use super::lock::Lock;
+use super::{layout::child_path, local, specifier::Specifier};
 
-const NODE_MODULES_DIR: &str = "node_modules";
+pub const NODE_MODULES_DIR: &str = "node_modules";
+/// Records the source an installed package came from, so switching it to
+/// another source with the same version installs it again.
+const SOURCE_FILE: &str = ".nana-source";
 
 #[derive(Debug)]
 pub struct NodeModules {
-    modules: Vec<(String, PathBuf)>,
+    /// Install paths of the installed packages.
+    modules: HashSet<String>,
 }
 
 impl NodeModules {
-    pub fn from_local_dir() -> NanaResult<Self> {
-        let modules = match std::fs::read_dir(NODE_MODULES_DIR) {
-            Ok(dir) => dir
-                .into_iter()
-                .filter_map(|p| p.ok())
-                .filter(|e| e.metadata().unwrap().is_dir())
-                .map(|f| (f.file_name().to_str().unwrap().to_string(), f.path()))
-                .collect::<Vec<(String, PathBuf)>>(),
-            Err(_) => vec![],
-        };
+    /// Scans `node_modules`, looking into the symlinked packages at
+    /// `workspaces` as their `node_modules` are managed from the root.
+    pub fn from_local_dir(workspaces: &[String]) -> NanaResult<Self> {
+        let mut modules = HashSet::new();
+        scan_dir("", workspaces, &mut modules);
 
         Ok(NodeModules { modules })
     }
 
-    pub fn matches(&self, lock: &Lock) -> bool {
-        let dependencies = lock.flat_dependencies();
-        let names = dependencies
+    /// Dependencies that are not installed, or installed from another source.
+    pub fn missing(
+        &self,
+        dependencies: &[(String, MetadataVersion)],
+    ) -> Vec<(String, MetadataVersion)> {
+        dependencies
+            .iter()
+            .filter(|(path, meta_version)| !is_installed(path, meta_version))
+            .cloned()
+            .collect()
+    }
+
+    /// Deletes installed packages that are not part of `dependencies`,
+    /// returning their install paths.
+    pub fn prune(&self, dependencies: &[(String, MetadataVersion)]) -> NanaResult<Vec<String>> {
+        let expected: HashSet<&String> = dependencies.iter().map(|(path, _)| path).collect();
+        let mut extraneous: Vec<&String> = self
+            .modules
             .iter()
-            .map(|v| &v.name)
-            .collect::<Vec<&String>>();
+            .filter(|path| !expected.contains(path))
+            .collect();
+        extraneous.sort();
+
+        let mut removed: Vec<String> = vec![];
+        for path in extraneous {
+            // Packages nested in an already removed one are gone with it
+            if removed.iter().any(|r| path.starts_with(&format!("{}/", r))) {
+                continue;
+            }
+
+            std::fs::remove_dir_all(path)?;
+            remove_empty_scope(path)?;
+            removed.push(path.clone());
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Collects every package installed under `parent`'s `node_modules`,
+/// descending into `@scope` and nested `node_modules` directories.
+fn scan_dir(parent: &str, workspaces: &[String], modules: &mut HashSet<String>) {
+    let dir = match parent.is_empty() {
+        true => NODE_MODULES_DIR.to_string(),
+        false => format!("{}/{}", parent, NODE_MODULES_DIR),
+    };
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
 
-        if self.modules.len() < names.len() {
-            return false;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || !entry.path().is_dir() {
+            continue;
         }
 
-        self.modules
+        if !name.starts_with('@') {
+            scan_package(parent, &name, workspaces, modules);
+            continue;
+        }
+
+        let scoped = match std::fs::read_dir(entry.path()) {
+            Ok(scoped) => scoped,
+            Err(_) => continue,
+        };
+
+        for entry in scoped.filter_map(|e| e.ok()) {
+            if entry.path().is_dir() {
+                let scoped_name = entry.file_name().to_string_lossy().to_string();
+                scan_package(
+                    parent,
+                    &format!("{}/{}", name, scoped_name),
+                    workspaces,
+                    modules,
+                );
+            }
+        }
+    }
+}
+
+fn scan_package(parent: &str, name: &str, workspaces: &[String], modules: &mut HashSet<String>) {
+    let path = child_path(parent, name);
+    if installed_version(&path).is_some() {
+        modules.insert(path.clone());
+    }
+
+    // Linked packages manage their own `node_modules`, workspaces excepted
+    if !Path::new(&path).is_symlink() || workspaces.contains(&path) {
+        scan_dir(&path, workspaces, modules);
+    }
+}
+
+/// Deletes the `node_modules` of the project and of its workspaces. Linked
+/// workspaces are removed as links, leaving their directory alone.
+pub fn remove_all(package: &Package) -> NanaResult<()> {
+    let dirs = std::iter::once(PathBuf::from(NODE_MODULES_DIR)).chain(
+        package
+            .workspaces()?
             .iter()
-            .map(|(n, _)| n)
-            .all(|i| names.contains(&i))
+            .map(|workspace| Path::new(&workspace.path).join(NODE_MODULES_DIR)),
+    );
+
+    for dir in dirs {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops the `@scope` directory `path` lived in once it has no packages left.
+fn remove_empty_scope(path: &str) -> NanaResult<()> {
+    let scope = match Path::new(path).parent() {
+        Some(scope) => scope,
+        None => return Ok(()),
+    };
+
+    let is_scope = scope
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('@'));
+
+    if is_scope && std::fs::read_dir(scope)?.next().is_none() {
+        std::fs::remove_dir(scope)?;
+    }
+
+    Ok(())
+}
+
+/// Whether `meta_version` is installed at `path` from the source it is
+/// locked to: linked to its target, or staged from its tarball.
+pub fn is_installed(path: &str, meta_version: &MetadataVersion) -> bool {
+    match Specifier::parse(&meta_version.dist.tarball) {
+        Specifier::Link(target) | Specifier::Workspace(target) => local::is_linked(path, &target),
+        _ => std::fs::read_to_string(Path::new(path).join(SOURCE_FILE))
+            .is_ok_and(|source| source == source_of(meta_version)),
     }
 }
+
+/// Marks the package staged at `dir` as installed from the source of
+/// `meta_version`.
+pub fn write_source(dir: &str, meta_version: &MetadataVersion) -> NanaResult<()> {
+    Ok(std::fs::write(
+        Path::new(dir).join(SOURCE_FILE),
+        source_of(meta_version),
+    )?)
+}
+
+fn source_of(meta_version: &MetadataVersion) -> String {
+    let dist = &meta_version.dist;
+    let hash = dist.integrity.as_ref().or(dist.shasum.as_ref());
+
+    // The real name, as an alias may switch to another package
+    format!(
+        "{}\n{}\n{}\n",
+        meta_version.name,
+        dist.tarball,
+        hash.map_or("", |hash| hash)
+    )
+}
+
+fn installed_version(path: &str) -> Option<String> {
+    let raw = std::fs::read_to_string(Path::new(path).join("package.json")).ok()?;
+    let manifest: serde_json::Value = serde_json::from_str(&raw).ok()?;
+
+    manifest["version"].as_str().map(|v| v.to_string())
+}
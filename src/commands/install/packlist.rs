@@ -0,0 +1,261 @@
+use std::path::Path;
+
+use bytes::Bytes;
+use flate2::{write::GzEncoder, Compression};
+
+use crate::{glob, package::manifest::Manifest, result::NanaResult};
+
+use super::modules::NODE_MODULES_DIR;
+
+/// Directories never published, wherever they are.
+const IGNORED_DIRS: [&str; 6] = [".git", ".svn", ".hg", "CVS", ".nana", NODE_MODULES_DIR];
+/// Files never published, wherever they are.
+const IGNORED_FILES: [&str; 7] = [
+    ".npmrc",
+    ".npmignore",
+    ".gitignore",
+    ".DS_Store",
+    "npm-debug.log",
+    "package-lock.json",
+    "nana.lock.yml",
+];
+/// Top-level files always published, even when `files` leaves them out.
+const ALWAYS_INCLUDED: [&str; 3] = ["readme", "license", "licence"];
+
+/// Files of the package in `dir` that `npm pack` would publish, relative to
+/// `dir`: the ones matched by `files` when present, otherwise everything not
+/// excluded by `.npmignore` (or `.gitignore` when there is none).
+pub fn packlist(dir: &Path) -> NanaResult<Vec<String>> {
+    let manifest = Manifest::from_dir(dir)?;
+    let main = manifest.main().map(normalize);
+    let files = manifest.files().map(|files| {
+        files
+            .iter()
+            .map(|pattern| normalize(pattern))
+            .collect::<Vec<String>>()
+    });
+
+    let ignore_rules = match files {
+        Some(_) => vec![],
+        None => ignore_rules(dir),
+    };
+
+    let mut result = vec![];
+    for path in walk(dir, "")? {
+        let is_included = match &files {
+            _ if is_always_included(&path, main.as_deref()) => true,
+            Some(files) => files.iter().any(|pattern| matches_path(pattern, &path)),
+            None => !is_ignored(&ignore_rules, &path),
+        };
+
+        if is_included {
+            result.push(path);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Tarball of the publishable files in `dir`, laid out like registry ones.
+pub fn pack(dir: &Path) -> NanaResult<Bytes> {
+    let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+
+    for path in packlist(dir)? {
+        builder.append_path_with_name(dir.join(&path), format!("package/{}", path))?;
+    }
+
+    Ok(Bytes::from(builder.into_inner()?.finish()?))
+}
+
+/// Every file below `dir`, skipping the directories and files that are
+/// never published.
+fn walk(dir: &Path, prefix: &str) -> NanaResult<Vec<String>> {
+    let mut result = vec![];
+
+    for entry in std::fs::read_dir(dir.join(prefix))?.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = match prefix.is_empty() {
+            true => name.clone(),
+            false => format!("{}/{}", prefix, name),
+        };
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if !IGNORED_DIRS.contains(&name.as_str()) {
+                result.extend(walk(dir, &path)?);
+            }
+        } else if file_type.is_file() && !is_ignored_file(&name) {
+            result.push(path);
+        }
+    }
+
+    result.sort();
+
+    Ok(result)
+}
+
+fn is_ignored_file(name: &str) -> bool {
+    IGNORED_FILES.contains(&name)
+        || name.ends_with(".orig")
+        || (name.starts_with('.') && name.ends_with(".swp"))
+}
+
+fn is_always_included(path: &str, main: Option<&str>) -> bool {
+    if path == "package.json" || Some(path) == main {
+        return true;
+    }
+
+    let lower = path.to_lowercase();
+    !path.contains('/') && ALWAYS_INCLUDED.iter().any(|name| lower.starts_with(name))
+}
+
+/// `(negated, pattern)` rules of the top-level ignore file.
+fn ignore_rules(dir: &Path) -> Vec<(bool, String)> {
+    let content = std::fs::read_to_string(dir.join(".npmignore"))
+        .or_else(|_| std::fs::read_to_string(dir.join(".gitignore")))
+        .unwrap_or_default();
+
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern.to_string()),
+            None => (false, line.to_string()),
+        })
+        .collect()
+}
+
+/// Like git, the last matching rule wins.
+fn is_ignored(rules: &[(bool, String)], path: &str) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|(_, pattern)| matches_ignore(pattern, path))
+        .is_some_and(|(negated, _)| !negated)
+}
+
+/// `.gitignore` semantics: a pattern without a `/` matches a file or
+/// directory name at any depth, one with a `/` is relative to the package
+/// root, and a trailing `/` only matches directories.
+fn matches_ignore(pattern: &str, path: &str) -> bool {
+    let dir_only = pattern.ends_with('/');
+    let pattern = pattern.trim_end_matches('/');
+    let anchored = pattern.contains('/');
+    let pattern = pattern.trim_start_matches('/');
+
+    let segments: Vec<&str> = path.split('/').collect();
+    // Directories containing `path`, then `path` itself
+    let candidates = match dir_only {
+        true => segments.len() - 1,
+        false => segments.len(),
+    };
+
+    (1..=candidates).any(|end| match anchored {
+        true => glob::matches(pattern, &segments[..end].join("/")),
+        false => glob::matches(pattern, segments[end - 1]),
+    })
+}
+
+/// A `files` entry matches the file itself or any directory containing it.
+fn matches_path(pattern: &str, path: &str) -> bool {
+    let segments: Vec<&str> = path.split('/').collect();
+
+    (1..=segments.len()).any(|end| glob::matches(pattern, &segments[..end].join("/")))
+}
+
+fn normalize(pattern: &str) -> String {
+    pattern
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .trim_end_matches('/')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn package(manifest: &str, files: &[&str]) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("package.json"), manifest).unwrap();
+
+        for path in files {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn includes_files_only() {
+        let dir = package(
+            r#"{"name":"a","main":"index.js","files":["lib/","types/*.d.ts"]}"#,
+            &[
+                "index.js",
+                "README.md",
+                "lib/a.js",
+                "lib/nested/b.js",
+                "types/a.d.ts",
+                "types/a.ts",
+                "test/a.js",
+                "lib/node_modules/c/index.js",
+            ],
+        );
+
+        assert_eq!(
+            packlist(dir.path()).unwrap(),
+            vec![
+                "README.md",
+                "index.js",
+                "lib/a.js",
+                "lib/nested/b.js",
+                "package.json",
+                "types/a.d.ts",
+            ]
+        );
+    }
+
+    #[test]
+    fn excludes_npmignore() {
+        let dir = package(
+            r#"{"name":"a"}"#,
+            &[
+                ".gitignore",
+                ".npmignore",
+                "dist/a.js",
+                "src/a.ts",
+                "src/keep.ts",
+                "docs/a.md",
+                ".git/HEAD",
+            ],
+        );
+        std::fs::write(
+            dir.path().join(".npmignore"),
+            "# sources\nsrc\n!keep.ts\n/docs/\n",
+        )
+        .unwrap();
+        // Ignored in favor of `.npmignore`
+        std::fs::write(dir.path().join(".gitignore"), "dist\n").unwrap();
+
+        assert_eq!(
+            packlist(dir.path()).unwrap(),
+            vec!["dist/a.js", "package.json", "src/keep.ts"]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_gitignore() {
+        let dir = package(r#"{"name":"a"}"#, &[".gitignore", "dist/a.js", "index.js"]);
+        std::fs::write(dir.path().join(".gitignore"), "dist/\n").unwrap();
+
+        assert_eq!(
+            packlist(dir.path()).unwrap(),
+            vec!["index.js", "package.json"]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::path::{Component, Path};
+
+use semver_rs::Range;
+
+use super::git::GitSpec;
+
+pub const FILE_PREFIX: &str = "file:";
+pub const LINK_PREFIX: &str = "link:";
+pub const WORKSPACE_PREFIX: &str = "workspace:";
+pub const ALIAS_PREFIX: &str = "npm:";
+const ANY_VERSION: &str = "*";
+const TARBALL_EXTENSIONS: [&str; 3] = [".tgz", ".tar.gz", ".tar"];
+
+/// What a dependency's version in `package.json` refers to. Locked packages
+/// keep where they come from in `dist.tarball`, which parses back to the
+/// matching kind: a URL for registry packages and tarballs, `git+<url>#<sha>`,
+/// `file:<path>`, `link:<path>` or `workspace:<path>`.
+#[derive(Debug, Clone)]
+pub enum Specifier {
+    /// Semver range on registry versions, e.g. `^1.2.0`.
+    Range(String),
+    /// Registry dist-tag, e.g. `latest` or `next`.
+    Tag(String),
+    Git(GitSpec),
+    /// Tarball URL, e.g. `https://host/foo.tgz`.
+    Tarball(String),
+    /// Tarball on disk, e.g. `file:vendor/foo-1.0.0.tgz`.
+    LocalTarball(String),
+    /// Directory on disk copied into `node_modules`, e.g. `file:../foo`.
+    Directory(String),
+    /// Directory on disk symlinked into `node_modules`, e.g. `link:../foo`.
+    Link(String),
+    /// Registry package installed under another name, e.g. `npm:lodash@^4`,
+    /// with the real name and its range or tag.
+    Alias(String, String),
+    /// Package of the same workspace, whatever the range, e.g. `workspace:*`
+    /// or `workspace:^`. Locked ones hold the workspace directory instead.
+    Workspace(String),
+}
+
+impl Specifier {
+    pub fn parse(spec: &str) -> Self {
+        let spec = spec.trim();
+
+        if let Some(target) = spec.strip_prefix(ALIAS_PREFIX) {
+            let (name, version_range) = parse_spec(target);
+            return Self::Alias(name, version_range.unwrap_or(ANY_VERSION.to_string()));
+        }
+
+        if let Some(rest) = spec.strip_prefix(WORKSPACE_PREFIX) {
+            return Self::Workspace(rest.to_string());
+        }
+
+        if let Some(path) = spec.strip_prefix(LINK_PREFIX) {
+            return Self::Link(path.to_string());
+        }
+
+        if let Some(path) = spec.strip_prefix(FILE_PREFIX).or_else(|| as_path(spec)) {
+            return match TARBALL_EXTENSIONS.iter().any(|ext| path.ends_with(ext)) {
+                true => Self::LocalTarball(path.to_string()),
+                false => Self::Directory(path.to_string()),
+            };
+        }
+
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            return Self::Tarball(spec.to_string());
+        }
+
+        if let Some(git) = GitSpec::parse(spec) {
+            return Self::Git(git);
+        }
+
+        match Range::new(spec).parse() {
+            Ok(_) => Self::Range(spec.to_string()),
+            Err(_) => Self::Tag(spec.to_string()),
+        }
+    }
+
+    pub fn is_tag(&self) -> bool {
+        matches!(self, Self::Tag(_))
+    }
+}
+
+/// `spec` as declared by the package in `dir`, with a relative local path
+/// made relative to the project root instead, so dependencies of `file:`
+/// packages and workspaces are found wherever they are installed from.
+pub fn rebase(spec: &str, dir: &str) -> String {
+    match Specifier::parse(spec) {
+        Specifier::Directory(path) | Specifier::LocalTarball(path) => {
+            format!("{}{}", FILE_PREFIX, join(dir, &path))
+        }
+        Specifier::Link(path) => format!("{}{}", LINK_PREFIX, join(dir, &path)),
+        _ => spec.to_string(),
+    }
+}
+
+/// Splits `name[@range]`, taking care of the leading `@` of scoped names.
+pub fn parse_spec(spec: &str) -> (String, Option<String>) {
+    match spec.get(1..).and_then(|rest| rest.find('@')) {
+        Some(index) => (
+            spec[..index + 1].to_string(),
+            Some(spec[index + 2..].to_string()).filter(|range| !range.is_empty()),
+        ),
+        None => (spec.to_string(), None),
+    }
+}
+
+/// `path` relative to `dir`, with `.` and `..` resolved lexically.
+fn join(dir: &str, path: &str) -> String {
+    if Path::new(path).is_absolute() {
+        return path.to_string();
+    }
+
+    let mut parts: Vec<String> = vec![];
+    for component in Path::new(dir).join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if parts.last().is_some_and(|p| p != ".." && !p.is_empty()) => {
+                parts.pop();
+            }
+            Component::RootDir => parts.push(String::new()),
+            component => parts.push(component.as_os_str().to_string_lossy().to_string()),
+        }
+    }
+
+    match parts.is_empty() {
+        true => ".".to_string(),
+        false => parts.join("/"),
+    }
+}
+
+/// Plain relative or absolute paths count as `file:` ones.
+fn as_path(spec: &str) -> Option<&str> {
+    match spec.starts_with("./") || spec.starts_with("../") || spec.starts_with('/') {
+        true => Some(spec),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_registry_specifiers() {
+        assert!(matches!(Specifier::parse("^1.2.0"), Specifier::Range(r) if r == "^1.2.0"));
+        assert!(
+            matches!(Specifier::parse(" 1.x || >=3 "), Specifier::Range(r) if r == "1.x || >=3")
+        );
+        assert!(matches!(Specifier::parse("*"), Specifier::Range(_)));
+        assert!(matches!(Specifier::parse("next"), Specifier::Tag(t) if t == "next"));
+        assert!(matches!(
+            Specifier::parse("npm:@scope/name@^4"),
+            Specifier::Alias(name, range) if name == "@scope/name" && range == "^4"
+        ));
+        assert!(matches!(
+            Specifier::parse("npm:lodash"),
+            Specifier::Alias(name, range) if name == "lodash" && range == "*"
+        ));
+    }
+
+    #[test]
+    fn parses_local_specifiers() {
+        assert!(matches!(Specifier::parse("file:../a"), Specifier::Directory(p) if p == "../a"));
+        assert!(matches!(Specifier::parse("./a"), Specifier::Directory(p) if p == "./a"));
+        assert!(matches!(
+            Specifier::parse("file:vendor/a-1.0.0.tgz"),
+            Specifier::LocalTarball(p) if p == "vendor/a-1.0.0.tgz"
+        ));
+        assert!(matches!(Specifier::parse("link:../a"), Specifier::Link(p) if p == "../a"));
+        assert!(matches!(Specifier::parse("workspace:^"), Specifier::Workspace(w) if w == "^"));
+    }
+
+    #[test]
+    fn parses_remote_specifiers() {
+        assert!(matches!(
+            Specifier::parse("https://host/a.tgz"),
+            Specifier::Tarball(url) if url == "https://host/a.tgz"
+        ));
+        assert!(matches!(
+            Specifier::parse("github:org/repo#v1"),
+            Specifier::Git(spec) if spec.url == "https://github.com/org/repo.git"
+        ));
+        assert!(matches!(Specifier::parse("org/repo"), Specifier::Git(_)));
+    }
+
+    #[test]
+    fn splits_names() {
+        assert_eq!(parse_spec("a"), ("a".to_string(), None));
+        assert_eq!(parse_spec("a@"), ("a".to_string(), None));
+        assert_eq!(
+            parse_spec("a@^1"),
+            ("a".to_string(), Some("^1".to_string()))
+        );
+        assert_eq!(parse_spec("@s/a"), ("@s/a".to_string(), None));
+        assert_eq!(
+            parse_spec("@s/a@next"),
+            ("@s/a".to_string(), Some("next".to_string()))
+        );
+    }
+
+    #[test]
+    fn rebases_local_paths() {
+        assert_eq!(rebase("file:../b", "vendor/a"), "file:vendor/b");
+        assert_eq!(rebase("./b.tgz", "vendor/a"), "file:vendor/a/b.tgz");
+        assert_eq!(rebase("link:../../b", "a"), "link:../b");
+        assert_eq!(rebase("file:..", "a"), "file:.");
+        assert_eq!(rebase("file:/opt/b", "a"), "file:/opt/b");
+        assert_eq!(rebase("file:../b", "/opt/a"), "file:/opt/b");
+        assert_eq!(rebase("^1.0.0", "a"), "^1.0.0");
+        assert_eq!(rebase("workspace:*", "a"), "workspace:*");
+    }
+}
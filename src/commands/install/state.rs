@@ -1,8 +1,6 @@
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
-};
+use std::{collections::HashMap, sync::Arc};
 
+use console::style;
 use indicatif::{ProgressBar, ProgressStyle};
 use tokio::sync::Mutex;
 
@@ -16,7 +14,10 @@ pub struct State {
 #[derive(Debug)]
 pub struct SharedState {
     pub dependencies: HashMap<String, MetadataVersion>,
-    pub dependencies_in_progress: HashSet<String>,
+    pub resolved: HashMap<String, String>,
+    /// Requests in flight by `name@version_range`, counted as the same one
+    /// may be requested by several packages at once.
+    pub dependencies_in_progress: HashMap<String, usize>,
     pub progress: ProgressBar,
 }
 
@@ -32,7 +33,8 @@ impl State {
 
         let shared = Arc::new(Mutex::new(SharedState {
             dependencies: HashMap::new(),
-            dependencies_in_progress: HashSet::new(),
+            resolved: HashMap::new(),
+            dependencies_in_progress: HashMap::new(),
             progress: pb,
         }));
 
@@ -57,6 +59,41 @@ impl State {
         self.shared.lock().await.progress.inc(amount);
     }
 
+    /// Marks a request for `name@version_range` as in flight.
+    pub async fn package_started(&self, name: &str, version_range: &str) {
+        *self
+            .shared
+            .lock()
+            .await
+            .dependencies_in_progress
+            .entry(format!("{}@{}", name, version_range))
+            .or_default() += 1;
+    }
+
+    /// Marks a request for `name@version_range` as resolved (or skipped),
+    /// returning whether nothing else is left in progress.
+    pub async fn package_done(&self, name: &str, version_range: &str) -> bool {
+        let mut lock = self.shared.lock().await;
+        let key = format!("{}@{}", name, version_range);
+        if let Some(count) = lock.dependencies_in_progress.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                lock.dependencies_in_progress.remove(&key);
+            }
+        }
+        lock.progress.inc(1);
+
+        lock.dependencies_in_progress.is_empty()
+    }
+
+    pub async fn warn(&self, msg: String) {
+        self.shared
+            .lock()
+            .await
+            .progress
+            .suspend(|| println!("{} {}", style("WARN").yellow(), msg));
+    }
+
     pub async fn progress_finish(&self) {
         self.shared.lock().await.progress.finish_and_clear();
     }
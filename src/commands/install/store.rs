@@ -0,0 +1,439 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{Cursor, Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use indexmap::IndexMap;
+use ssri::{Algorithm, Integrity, IntegrityOpts};
+use tar::{Archive, EntryType};
+
+use crate::{
+    config::Config,
+    package::metadata::MetadataVersion,
+    result::{NanaError, NanaResult},
+};
+
+/// Bumped whenever the layout of the store changes.
+const STORE_VERSION: &str = "v2";
+const FILES_DIR: &str = "files";
+const INDEX_DIR: &str = "index";
+const EXECUTABLE_SUFFIX: &str = "-exec";
+const COPY_BUFFER: usize = 64 * 1024;
+
+/// Distinguishes temporary files written concurrently by this process.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Files of a stored package: path inside the package to the name of its
+/// content in the store.
+pub type Index = IndexMap<String, String>;
+
+/// Content-addressable package store shared by every project of the user.
+/// Each file is stored once, named after the hash of its content (plus its
+/// executable bit), and each package tarball has an index of its files
+/// keyed by the tarball's integrity. Packages are installed by hard linking
+/// their files into `node_modules`.
+#[derive(Debug, Clone)]
+pub struct Store {
+    dir: PathBuf,
+    /// Files found intact by this instance, checked once however many
+    /// packages share them.
+    verified: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Store {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            dir: config.store_dir().join(STORE_VERSION),
+            verified: Arc::default(),
+        }
+    }
+
+    /// Index of `meta_version` if it is stored with all of its files intact.
+    pub fn index(&self, meta_version: &MetadataVersion) -> Option<Index> {
+        let path = self.index_path(meta_version)?;
+        let saved_at = std::fs::metadata(&path).ok()?.modified().ok()?;
+        let index: Index = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+
+        index
+            .values()
+            .all(|name| self.is_intact(name, saved_at))
+            .then_some(index)
+    }
+
+    /// Whether the stored file `name` still matches its name. Files changed
+    /// since `saved_at`, e.g. through one of their hard links, are hashed
+    /// again, and removed if they no longer match so they get stored anew.
+    /// Each file is only checked once per `Store`, i.e. per install.
+    fn is_intact(&self, name: &str, saved_at: SystemTime) -> bool {
+        if self.verified.lock().unwrap().contains(name) {
+            return true;
+        }
+
+        let path = self.file_path(name);
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+
+        let is_executable = name.ends_with(EXECUTABLE_SUFFIX);
+        let is_intact = has_mode(&metadata, is_executable)
+            && match metadata.modified() {
+                Ok(modified) if modified <= saved_at => true,
+                _ => hash_file(&path)
+                    .is_ok_and(|hex| hex == name.trim_end_matches(EXECUTABLE_SUFFIX)),
+            };
+
+        if is_intact {
+            self.verified.lock().unwrap().insert(name.to_string());
+        } else {
+            let _ = std::fs::remove_file(&path);
+        }
+
+        is_intact
+    }
+
+    /// Stores the files of the verified tarball of `meta_version`, returning
+    /// its index. Packages without known hashes cannot be looked up again and
+    /// are not stored.
+    pub fn add(&self, meta_version: &MetadataVersion, bytes: &Bytes) -> NanaResult<Option<Index>> {
+        if !self.can_store(meta_version) {
+            return Ok(None);
+        }
+
+        let index = self.unpack(Cursor::new(bytes))?;
+        self.save_index(meta_version, &index)?;
+
+        Ok(Some(index))
+    }
+
+    /// Whether `meta_version` has a hash its index can be keyed by.
+    fn can_store(&self, meta_version: &MetadataVersion) -> bool {
+        self.index_path(meta_version).is_some()
+    }
+
+    /// Writes the files of the gzipped `tarball` as it is read, returning
+    /// their index. Nothing refers to them until the index is saved.
+    pub fn unpack(&self, tarball: impl Read) -> NanaResult<Index> {
+        let mut index = Index::new();
+        let mut archive = Archive::new(GzDecoder::new(tarball));
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            // Like npm, only regular files are kept
+            if entry.header().entry_type() != EntryType::Regular {
+                continue;
+            }
+
+            let path = match package_path(&entry.path()?) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let is_executable = entry.header().mode().unwrap_or(0) & 0o111 != 0;
+            let name = self.write_file(&mut entry, is_executable)?;
+
+            index.insert(path, name);
+        }
+
+        Ok(index)
+    }
+
+    /// Saves `index` as the one of `meta_version`, once its tarball is
+    /// verified.
+    pub fn save_index(&self, meta_version: &MetadataVersion, index: &Index) -> NanaResult<()> {
+        match self.index_path(meta_version) {
+            Some(path) => write_atomic(&path, &serde_json::to_vec(index)?, false),
+            None => Ok(()),
+        }
+    }
+
+    /// Installs the files of `index` at `path`, as hard links when possible.
+    /// Copies are made across filesystems, which reflink where supported.
+    pub fn link(&self, index: &Index, path: &str) -> NanaResult<()> {
+        self.place(index, path, true)
+    }
+
+    /// Installs copies of the files of `index` at `path`, for packages that
+    /// may change them in place.
+    pub fn copy(&self, index: &Index, path: &str) -> NanaResult<()> {
+        self.place(index, path, false)
+    }
+
+    fn place(&self, index: &Index, path: &str, hard_link: bool) -> NanaResult<()> {
+        let package_dir = Path::new(path);
+
+        for (file, name) in index {
+            let destination = package_dir.join(file);
+            if let Some(parent) = destination.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let source = self.file_path(name);
+            if !hard_link || std::fs::hard_link(&source, &destination).is_err() {
+                std::fs::copy(&source, &destination)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes `content` unless already stored, returning its name. Files can
+    /// be large, so they are hashed while streamed to a temporary file, which
+    /// is removed if anything fails.
+    fn write_file(&self, content: impl Read, is_executable: bool) -> NanaResult<String> {
+        let temp = temp_path(&self.dir.join(FILES_DIR))?;
+
+        let result = self.store_temp(&temp, content, is_executable);
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+
+        result
+    }
+
+    /// Streams `content` to `temp`, then moves it to its name in the store.
+    fn store_temp(
+        &self,
+        temp: &Path,
+        mut content: impl Read,
+        is_executable: bool,
+    ) -> NanaResult<String> {
+        let mut hasher = IntegrityOpts::new().algorithm(Algorithm::Sha512);
+
+        let mut file = File::create(temp)?;
+        let mut buf = [0; COPY_BUFFER];
+        loop {
+            let read = content.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+
+            hasher.input(&buf[..read]);
+            file.write_all(&buf[..read])?;
+        }
+        drop(file);
+
+        let (_, hex) = hasher.result().to_hex();
+        let name = match is_executable {
+            true => format!("{}{}", hex, EXECUTABLE_SUFFIX),
+            false => hex,
+        };
+
+        let path = self.file_path(&name);
+        if path.exists() {
+            std::fs::remove_file(temp)?;
+        } else {
+            std::fs::create_dir_all(path.parent().unwrap_or(&self.dir))?;
+            set_executable(temp, is_executable)?;
+            std::fs::rename(temp, &path)?;
+        }
+
+        Ok(name)
+    }
+
+    fn file_path(&self, name: &str) -> PathBuf {
+        self.dir.join(FILES_DIR).join(&name[..2]).join(&name[2..])
+    }
+
+    /// Where the index of `meta_version` lives, keyed by the strongest hash
+    /// of its tarball.
+    fn index_path(&self, meta_version: &MetadataVersion) -> Option<PathBuf> {
+        let (algorithm, hex) = match (&meta_version.dist.integrity, &meta_version.dist.shasum) {
+            (Some(integrity), _) => {
+                let (algorithm, hex) = integrity.parse::<Integrity>().ok()?.to_hex();
+                (algorithm.to_string(), hex)
+            }
+            (None, Some(shasum)) => ("sha1".to_string(), shasum.to_lowercase()),
+            (None, None) => return None,
+        };
+
+        Some(self.dir.join(INDEX_DIR).join(&hex[..2]).join(format!(
+            "{}-{}.json",
+            algorithm,
+            &hex[2..]
+        )))
+    }
+}
+
+/// Path of a tarball entry inside the package, without the top-level
+/// directory tarballs are wrapped in. Entries escaping it are dropped.
+fn package_path(entry_path: &Path) -> Option<String> {
+    let path: PathBuf = entry_path.components().skip(1).collect();
+
+    if path.as_os_str().is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    Some(path.to_string_lossy().replace('\\', "/"))
+}
+
+/// Hex sha512 of the file at `path`, as stored files are named.
+fn hash_file(path: &Path) -> NanaResult<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = IntegrityOpts::new().algorithm(Algorithm::Sha512);
+
+    let mut buf = [0; COPY_BUFFER];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.input(&buf[..read]);
+    }
+
+    Ok(hasher.result().to_hex().1)
+}
+
+/// Writes through a temporary file, so concurrent installs never see a
+/// partially written file.
+fn write_atomic(path: &Path, content: &[u8], is_executable: bool) -> NanaResult<()> {
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => {
+            return Err(NanaError::Runtime(format!(
+                "Invalid store path '{}'",
+                path.display()
+            )))
+        }
+    };
+
+    let temp = temp_path(parent)?;
+    let result = std::fs::write(&temp, content)
+        .map_err(NanaError::from)
+        .and_then(|_| set_executable(&temp, is_executable))
+        .and_then(|_| Ok(std::fs::rename(&temp, path)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+
+    result
+}
+
+/// Unique temporary path in `dir`, created if needed.
+fn temp_path(dir: &Path) -> NanaResult<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    Ok(dir.join(format!(
+        ".tmp-{}-{}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )))
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path, is_executable: bool) -> NanaResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = match is_executable {
+        true => 0o755,
+        false => 0o644,
+    };
+
+    Ok(std::fs::set_permissions(
+        path,
+        std::fs::Permissions::from_mode(mode),
+    )?)
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path, _is_executable: bool) -> NanaResult<()> {
+    Ok(())
+}
+
+/// Whether a stored file still has the mode it was stored with.
+#[cfg(unix)]
+fn has_mode(metadata: &std::fs::Metadata, is_executable: bool) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    let expected = match is_executable {
+        true => 0o755,
+        false => 0o644,
+    };
+
+    metadata.permissions().mode() & 0o777 == expected
+}
+
+#[cfg(not(unix))]
+fn has_mode(_metadata: &std::fs::Metadata, _is_executable: bool) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails once `limit` bytes have been read.
+    struct Failing {
+        read: usize,
+        limit: usize,
+    }
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.read >= self.limit {
+                return Err(std::io::Error::other("broken"));
+            }
+
+            let len = buf.len().min(self.limit - self.read);
+            buf[..len].fill(b'a');
+            self.read += len;
+
+            Ok(len)
+        }
+    }
+
+    fn store() -> (tempfile::TempDir, Store) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store {
+            dir: dir.path().join(STORE_VERSION),
+            verified: Arc::default(),
+        };
+
+        (dir, store)
+    }
+
+    #[test]
+    fn removes_temp_file_on_error() {
+        let (_dir, store) = store();
+
+        let content = Failing {
+            read: 0,
+            limit: COPY_BUFFER * 2,
+        };
+        assert!(store.write_file(content, false).is_err());
+
+        let files = std::fs::read_dir(store.dir.join(FILES_DIR)).unwrap();
+        assert_eq!(files.count(), 0);
+    }
+
+    #[test]
+    fn detects_tampered_files() {
+        let (_dir, store) = store();
+        let saved_at = SystemTime::UNIX_EPOCH;
+
+        let name = store.write_file(&b"content"[..], false).unwrap();
+        assert!(store.is_intact(&name, saved_at));
+
+        std::fs::write(store.file_path(&name), "changed").unwrap();
+        // Checked once per run, the next one sees the change
+        assert!(store.is_intact(&name, saved_at));
+        let store = Store {
+            verified: Arc::default(),
+            ..store
+        };
+        assert!(!store.is_intact(&name, saved_at));
+        // Removed, so it gets stored again
+        assert!(!store.file_path(&name).exists());
+    }
+}
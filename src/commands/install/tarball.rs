@@ -0,0 +1,89 @@
+use std::{io::Cursor, path::Path};
+
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use ssri::{Algorithm, IntegrityOpts};
+use tar::Archive;
+
+use crate::{
+    package::{
+        metadata::{Dist, MetadataVersion},
+        PACKAGE_NAME,
+    },
+    result::{NanaError, NanaResult},
+};
+
+use super::{fetch::Fetcher, integrity, specifier::FILE_PREFIX, store::Store};
+
+/// Resolves a tarball URL by downloading it, its integrity is pinned in the
+/// lock so later installs get the very same content. The tarball is added to
+/// `store` right away, so it is not downloaded again to be installed.
+pub async fn resolve_remote(
+    fetcher: &Fetcher,
+    store: &Store,
+    name: &str,
+    url: &str,
+) -> NanaResult<MetadataVersion> {
+    let bytes = fetcher.fetch_tarball(url).await?;
+    let meta_version = metadata_version(name, url.to_string(), &bytes)?;
+
+    let store = store.clone();
+    tokio::task::spawn_blocking(move || {
+        store.add(&meta_version, &bytes)?;
+
+        Ok(meta_version)
+    })
+    .await?
+}
+
+pub fn resolve_local(name: &str, path: &str) -> NanaResult<MetadataVersion> {
+    let bytes = std::fs::read(path)?;
+
+    metadata_version(name, format!("{}{}", FILE_PREFIX, path), &bytes)
+}
+
+/// Reads a tarball on disk, checking it still matches the locked integrity.
+pub fn read_local(path: &str, meta_version: &MetadataVersion) -> NanaResult<Bytes> {
+    let bytes = std::fs::read(path)?;
+    integrity::verify(meta_version, &bytes)?;
+
+    Ok(Bytes::from(bytes))
+}
+
+fn metadata_version(name: &str, tarball: String, bytes: &[u8]) -> NanaResult<MetadataVersion> {
+    let mut hasher = IntegrityOpts::new().algorithm(Algorithm::Sha512);
+    hasher.input(bytes);
+
+    let dist = Dist {
+        tarball,
+        shasum: None,
+        integrity: Some(hasher.result().to_string()),
+    };
+
+    MetadataVersion::from_manifest(name, &read_manifest(name, bytes)?, dist)
+}
+
+/// Content of the top-level `package.json`, whatever directory the tarball
+/// wraps its files in.
+fn read_manifest(name: &str, bytes: &[u8]) -> NanaResult<Vec<u8>> {
+    let mut archive = Archive::new(GzDecoder::new(Cursor::new(bytes)));
+
+    for mut entry in archive.entries()?.filter_map(|e| e.ok()) {
+        let is_manifest = entry
+            .path()?
+            .components()
+            .skip(1)
+            .eq(Path::new(PACKAGE_NAME).components());
+
+        if is_manifest {
+            let mut content = vec![];
+            std::io::Read::read_to_end(&mut entry, &mut content)?;
+            return Ok(content);
+        }
+    }
+
+    Err(NanaError::Runtime(format!(
+        "Tarball of '{}' has no '{}'",
+        name, PACKAGE_NAME
+    )))
+}
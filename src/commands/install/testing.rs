@@ -0,0 +1,78 @@
+use serde_json::{json, Map, Value};
+
+use crate::{
+    config::DEFAULT_REGISTRY,
+    package::metadata::{Dist, MetadataVersion},
+};
+
+/// Builds the `MetadataVersion` of a package published to the default
+/// registry, its source and dependencies overridable.
+pub struct TestPackage {
+    name: String,
+    version: String,
+    dependencies: Vec<(String, String)>,
+    optional_dependencies: Vec<(String, String)>,
+    dist: Dist,
+}
+
+impl TestPackage {
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            dependencies: vec![],
+            optional_dependencies: vec![],
+            dist: Dist {
+                tarball: format!("{0}{1}/-/{1}-{2}.tgz", DEFAULT_REGISTRY, name, version),
+                shasum: None,
+                integrity: None,
+            },
+        }
+    }
+
+    pub fn dependency(mut self, name: &str, version_range: &str) -> Self {
+        self.dependencies
+            .push((name.to_string(), version_range.to_string()));
+        self
+    }
+
+    pub fn optional_dependency(mut self, name: &str, version_range: &str) -> Self {
+        self.optional_dependencies
+            .push((name.to_string(), version_range.to_string()));
+        self
+    }
+
+    pub fn tarball(mut self, tarball: &str) -> Self {
+        self.dist.tarball = tarball.to_string();
+        self
+    }
+
+    pub fn shasum(mut self, shasum: Option<&str>) -> Self {
+        self.dist.shasum = shasum.map(String::from);
+        self
+    }
+
+    pub fn integrity(mut self, integrity: Option<&str>) -> Self {
+        self.dist.integrity = integrity.map(String::from);
+        self
+    }
+
+    pub fn build(self) -> MetadataVersion {
+        let manifest = json!({
+            "name": self.name,
+            "version": self.version,
+            "dependencies": section(self.dependencies),
+            "optionalDependencies": section(self.optional_dependencies),
+        });
+
+        MetadataVersion::from_manifest(&self.name, manifest.to_string().as_bytes(), self.dist)
+            .unwrap()
+    }
+}
+
+fn section(dependencies: Vec<(String, String)>) -> Map<String, Value> {
+    dependencies
+        .into_iter()
+        .map(|(name, version_range)| (name, Value::String(version_range)))
+        .collect()
+}
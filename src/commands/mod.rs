@@ -1,3 +1,5 @@
+pub mod add;
 pub mod init;
 pub mod install;
+pub mod remove;
 pub mod run;
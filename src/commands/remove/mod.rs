@@ -0,0 +1,40 @@
+use console::style;
+
+use crate::{
+    commands::install::{Install, InstallOptions},
+    config::Config,
+    package::manifest::Manifest,
+    result::{NanaError, NanaResult, PackageError},
+};
+
+pub struct Remove {
+    config: Config,
+}
+
+impl Remove {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+}
+
+impl Remove {
+    pub async fn run(&self, names: &[String]) -> NanaResult<()> {
+        let mut manifest = Manifest::from_local_package()?;
+
+        for name in names {
+            if !manifest.remove_dependency(name) {
+                return Err(NanaError::Package(PackageError::DependencyNotFound(
+                    name.clone(),
+                )));
+            }
+
+            println!("Removing {}", style(name).bold());
+        }
+
+        manifest.save()?;
+
+        // Installing prunes whatever the lock and `node_modules` no longer need
+        let mut install = Install::new(self.config.clone(), InstallOptions::default());
+        install.run().await
+    }
+}
@@ -1,48 +1,141 @@
-use std::process::Command;
+mod workspaces;
+
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+};
+
+use console::style;
 
 use crate::{
-    package::Package,
+    config::Config,
+    package::{manifest::Manifest, Package},
     result::{NanaError, NanaResult, PackageError},
+    script,
 };
 
-pub struct RunScript {}
+pub use self::workspaces::WorkspaceOptions;
+
+pub struct RunScript {
+    config: Config,
+}
 
 impl RunScript {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(config: Config) -> Self {
+        Self { config }
     }
 
-    pub fn run(&self, name: &str) -> NanaResult<()> {
+    /// Runs script `name` with `args` appended, wrapped in its `pre<name>`
+    /// and `post<name>` hooks when defined. The first failing script stops
+    /// the chain.
+    pub fn run(&self, name: &str, args: &[String], if_present: bool) -> NanaResult<()> {
         let package = Package::from_local_package()?;
-        let script = package.script(name);
 
-        match script {
-            Some(cmd) => {
-                run_command(&cmd)?;
-            }
+        match package.script(name) {
+            Some(_) => self.run_with_hooks(&package, Path::new("."), name, args, None),
+            None if if_present => Ok(()),
+            None => Err(NanaError::Package(PackageError::ScriptNotFound(
+                name.into(),
+            ))),
+        }
+    }
+}
+
+impl RunScript {
+    /// Runs script `name` of `package`, living in `dir`, along with its hooks.
+    /// Output lines are tagged with `prefix` when set.
+    fn run_with_hooks(
+        &self,
+        package: &Package,
+        dir: &Path,
+        name: &str,
+        args: &[String],
+        prefix: Option<&str>,
+    ) -> NanaResult<()> {
+        let manifest = Manifest::from_dir(dir)?;
+
+        let pre = format!("pre{}", name);
+        if let Some(cmd) = package.script(&pre) {
+            self.run_command(&manifest, dir, &pre, &cmd, prefix)?;
+        }
+
+        if let Some(cmd) = package.script(name) {
+            let cmd = script::with_args(&cmd, args);
+            self.run_command(&manifest, dir, name, &cmd, prefix)?;
+        }
+
+        let post = format!("post{}", name);
+        if let Some(cmd) = package.script(&post) {
+            self.run_command(&manifest, dir, &post, &cmd, prefix)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `cmd` through the shell from `dir`, and fails with the child's
+    /// exit code if it does not succeed. Without `prefix` nana's stdio is
+    /// inherited, otherwise every output line is tagged with it.
+    fn run_command(
+        &self,
+        manifest: &Manifest,
+        dir: &Path,
+        name: &str,
+        cmd: &str,
+        prefix: Option<&str>,
+    ) -> NanaResult<()> {
+        let header = format!("{} {}", style(format!("> {}", name)).bold(), cmd);
+
+        let mut command = script::command(cmd, dir)?;
+        command.envs(script::environment(manifest, &self.config, name, cmd)?);
+
+        let status = match prefix {
             None => {
-                return Err(NanaError::Package(PackageError::ScriptNotFound(
-                    name.into(),
-                )))
+                println!("{}", header);
+                command.status()?
+            }
+            Some(prefix) => {
+                println!("{} {}", prefix, header);
+                forward_output(command, prefix)?
             }
+        };
+
+        if status.success() {
+            return Ok(());
         }
 
-        Ok(())
+        Err(NanaError::Package(PackageError::ScriptFailed(
+            name.to_string(),
+            script::exit_code(&status),
+        )))
     }
 }
 
-fn run_command(cmd: &str) -> NanaResult<()> {
-    if cmd.starts_with("nana ") {
-        Command::new("nana")
-            .arg(cmd.strip_prefix("nana ").unwrap_or_default())
-            .spawn()?;
-    } else if cmd.starts_with("node ") {
-        Command::new("node")
-            .arg(cmd.strip_prefix("node ").unwrap_or_default())
-            .spawn()?;
-    } else {
-        Command::new("node").arg(cmd).spawn()?;
+/// Runs `command`, printing each line of its stdout and stderr to nana's own
+/// as soon as it is complete, tagged with `prefix`.
+fn forward_output(mut command: Command, prefix: &str) -> NanaResult<ExitStatus> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stderr = child.stderr.take();
+    let stderr_prefix = prefix.to_string();
+    let stderr_handle = std::thread::spawn(move || {
+        if let Some(stderr) = stderr {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                eprintln!("{} {}", stderr_prefix, line);
+            }
+        }
+    });
+
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            println!("{} {}", prefix, line);
+        }
     }
 
-    Ok(())
+    let _ = stderr_handle.join();
+
+    Ok(child.wait()?)
 }
@@ -0,0 +1,316 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use console::style;
+
+use crate::{
+    glob,
+    package::{workspace::Workspace, Package},
+    result::{NanaError, NanaResult, PackageError},
+};
+
+use super::RunScript;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const PATH_FILTER_PREFIX: &str = "./";
+
+/// How a script runs across the workspaces of a monorepo.
+#[derive(Debug, Clone)]
+pub struct WorkspaceOptions {
+    /// Workspace names, name globs (e.g. `@acme/*`) or directory globs
+    /// starting with `./` (e.g. `./packages/*`). Every workspace when empty.
+    pub filters: Vec<String>,
+    /// Starts every workspace at once instead of waiting for the workspaces
+    /// they depend on.
+    pub parallel: bool,
+    /// Maximum number of workspaces running at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for WorkspaceOptions {
+    fn default() -> Self {
+        Self {
+            filters: vec![],
+            parallel: false,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Succeeded,
+    Failed(i32),
+    Skipped,
+}
+
+/// Workspace the script runs in, and the targets it has to wait for.
+struct Target {
+    workspace: Workspace,
+    package: Package,
+    dependencies: Vec<usize>,
+}
+
+impl RunScript {
+    /// Runs script `name` in every selected workspace defining it, each one
+    /// after the workspaces it depends on. Once a script fails, no other
+    /// workspace is started and the ones left are reported as skipped.
+    pub fn run_workspaces(
+        &self,
+        name: &str,
+        args: &[String],
+        if_present: bool,
+        options: &WorkspaceOptions,
+    ) -> NanaResult<()> {
+        let root = Package::from_local_package()?;
+
+        let selected: Vec<&Workspace> = root
+            .workspaces()?
+            .iter()
+            .filter(|workspace| is_selected(workspace, &options.filters))
+            .collect();
+
+        if selected.is_empty() {
+            return Err(NanaError::Runtime(
+                "No workspace matches the given filters".to_string(),
+            ));
+        }
+
+        let mut targets = vec![];
+        for workspace in selected {
+            let package = Package::from_dir(Path::new(&workspace.path))?;
+            if package.script(name).is_some() {
+                targets.push(Target {
+                    workspace: workspace.clone(),
+                    package,
+                    dependencies: vec![],
+                });
+            }
+        }
+
+        if targets.is_empty() {
+            return match if_present {
+                true => Ok(()),
+                false => Err(NanaError::Package(PackageError::ScriptNotFound(
+                    name.into(),
+                ))),
+            };
+        }
+
+        if !options.parallel {
+            link_dependencies(&mut targets, root.workspaces()?);
+        }
+
+        let concurrency = match options.parallel {
+            true => targets.len(),
+            false => options.concurrency.max(1),
+        };
+
+        let (statuses, durations, failure) = self.schedule(&targets, name, args, concurrency);
+
+        print_summary(name, &targets, &statuses, &durations);
+
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs every target as soon as its dependencies succeeded, at most
+    /// `concurrency` at a time. Dependency cycles are broken by starting a
+    /// waiting target whenever nothing else runs.
+    fn schedule(
+        &self,
+        targets: &[Target],
+        name: &str,
+        args: &[String],
+        concurrency: usize,
+    ) -> (Vec<Status>, Vec<Option<Duration>>, Option<NanaError>) {
+        let width = targets
+            .iter()
+            .map(|target| target.workspace.name.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut statuses: Vec<Option<Status>> = vec![None; targets.len()];
+        let mut durations: Vec<Option<Duration>> = vec![None; targets.len()];
+        let mut started = vec![false; targets.len()];
+        let mut running = 0;
+        let mut failure: Option<NanaError> = None;
+
+        let (tx, rx) = mpsc::channel::<(usize, NanaResult<()>, Duration)>();
+
+        std::thread::scope(|scope| loop {
+            while failure.is_none() && running < concurrency {
+                let is_ready = |index: usize| {
+                    !started[index]
+                        && targets[index]
+                            .dependencies
+                            .iter()
+                            .all(|&d| statuses[d] == Some(Status::Succeeded))
+                };
+
+                let next = (0..targets.len())
+                    .find(|&index| is_ready(index))
+                    .or_else(|| match running {
+                        0 => (0..targets.len()).find(|&index| !started[index]),
+                        _ => None,
+                    });
+
+                let index = match next {
+                    Some(index) => index,
+                    None => break,
+                };
+
+                started[index] = true;
+                running += 1;
+
+                let tx = tx.clone();
+                let target = &targets[index];
+                let prefix = style(format!(
+                    "{:<width$} |",
+                    target.workspace.name,
+                    width = width
+                ))
+                .cyan()
+                .to_string();
+
+                scope.spawn(move || {
+                    let start = Instant::now();
+                    let result = self.run_with_hooks(
+                        &target.package,
+                        Path::new(&target.workspace.path),
+                        name,
+                        args,
+                        Some(&prefix),
+                    );
+
+                    let _ = tx.send((index, result, start.elapsed()));
+                });
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            let (index, result, duration) = match rx.recv() {
+                Ok(message) => message,
+                Err(_) => break,
+            };
+
+            running -= 1;
+            durations[index] = Some(duration);
+            statuses[index] = Some(match result {
+                Ok(_) => Status::Succeeded,
+                Err(error) => {
+                    let code = error.exit_code();
+                    failure.get_or_insert(error);
+                    Status::Failed(code)
+                }
+            });
+        });
+
+        let statuses = statuses
+            .into_iter()
+            .map(|status| status.unwrap_or(Status::Skipped))
+            .collect();
+
+        (statuses, durations, failure)
+    }
+}
+
+/// Whether `workspace` matches any of `filters`, or there are none.
+fn is_selected(workspace: &Workspace, filters: &[String]) -> bool {
+    filters.is_empty()
+        || filters
+            .iter()
+            .any(|filter| match filter.strip_prefix(PATH_FILTER_PREFIX) {
+                Some(path) => glob::matches(path.trim_end_matches('/'), &workspace.path),
+                None => glob::matches(filter, &workspace.name),
+            })
+}
+
+/// Records, for every target, the other targets it depends on through any
+/// of its dependency sections. The graph spans every workspace, so targets
+/// also wait for the ones they reach through workspaces not running the
+/// script.
+fn link_dependencies(targets: &mut [Target], workspaces: &[Workspace]) {
+    let graph: HashMap<&str, Vec<String>> = workspaces
+        .iter()
+        .map(|workspace| {
+            let dependencies = workspace
+                .meta_version
+                .all_dependencies()
+                .into_iter()
+                .map(|(name, _, _)| name)
+                .collect();
+            (workspace.name.as_str(), dependencies)
+        })
+        .collect();
+
+    let names: Vec<String> = targets
+        .iter()
+        .map(|target| target.workspace.name.clone())
+        .collect();
+
+    for (index, target) in targets.iter_mut().enumerate() {
+        let mut reachable = HashSet::new();
+        let mut queue = vec![target.workspace.name.as_str()];
+
+        while let Some(name) = queue.pop() {
+            for dependency in graph.get(name).into_iter().flatten() {
+                if reachable.insert(dependency.as_str()) {
+                    queue.push(dependency);
+                }
+            }
+        }
+
+        target.dependencies = names
+            .iter()
+            .enumerate()
+            .filter(|&(dependency, name)| dependency != index && reachable.contains(name.as_str()))
+            .map(|(dependency, _)| dependency)
+            .collect();
+    }
+}
+
+fn print_summary(
+    name: &str,
+    targets: &[Target],
+    statuses: &[Status],
+    durations: &[Option<Duration>],
+) {
+    let width = targets
+        .iter()
+        .map(|target| target.workspace.name.len())
+        .max()
+        .unwrap_or(0);
+
+    println!();
+    println!("{}", style(format!("Summary of '{}'", name)).bold());
+
+    for ((target, status), duration) in targets.iter().zip(statuses).zip(durations) {
+        let status = match status {
+            Status::Succeeded => style(format!("{:<8}", "ok")).green(),
+            Status::Failed(code) => style(format!("{:<8}", format!("exit {}", code))).red(),
+            Status::Skipped => style(format!("{:<8}", "skipped")).dim(),
+        };
+
+        let duration = duration
+            .map(|duration| format!("{:.2}s", duration.as_secs_f64()))
+            .unwrap_or_default();
+
+        let line = format!(
+            "  {:<width$}  {} {}",
+            target.workspace.name,
+            status,
+            duration,
+            width = width
+        );
+        println!("{}", line.trim_end());
+    }
+}
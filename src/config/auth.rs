@@ -0,0 +1,187 @@
+use reqwest::Url;
+
+use super::Config;
+
+/// Settings holding credentials, either unscoped or prefixed with a
+/// `//host/path/:` registry.
+const AUTH_KEYS: [&str; 7] = [
+    "_authToken",
+    "_auth",
+    "username",
+    "_password",
+    "password",
+    "certfile",
+    "keyfile",
+];
+
+/// Credentials sent to a registry through the `Authorization` header.
+pub enum Auth {
+    Bearer(String),
+    /// Already base64 encoded `username:password`.
+    Basic(String),
+}
+
+impl Auth {
+    pub fn header(&self) -> String {
+        match self {
+            Auth::Bearer(token) => format!("Bearer {}", token),
+            Auth::Basic(credentials) => format!("Basic {}", credentials),
+        }
+    }
+}
+
+impl Config {
+    /// Credentials for a request to `url`, taken from the most specific
+    /// `//host/path/:` entry whose path contains the URL, so tarballs served
+    /// by the same registry get them too. Unscoped credentials are only sent
+    /// to the default `registry`.
+    pub fn auth(&self, url: &str) -> Option<Auth> {
+        let url = Url::parse(url).ok()?;
+        let mut prefix = nerf_dart(&url)?;
+
+        loop {
+            if let Some(auth) = self.auth_for(&format!("{}:", prefix)) {
+                return Some(auth);
+            }
+
+            // `//host/a/b/` -> `//host/a/`, stopping at `//host/`
+            let parent = prefix.trim_end_matches('/').rfind('/')?;
+            if parent < 2 {
+                break;
+            }
+            prefix.truncate(parent + 1);
+        }
+
+        let registry = Url::parse(&self.registry("")).ok()?;
+        match registry.host_str() == url.host_str() && registry.port() == url.port() {
+            true => self.auth_for(""),
+            false => None,
+        }
+    }
+
+    fn auth_for(&self, prefix: &str) -> Option<Auth> {
+        if let Some(token) = self.get(&format!("{}_authToken", prefix)) {
+            return Some(Auth::Bearer(token.to_string()));
+        }
+
+        if let Some(credentials) = self.get(&format!("{}_auth", prefix)) {
+            return Some(Auth::Basic(credentials.to_string()));
+        }
+
+        // Like npm, `_password` is stored base64 encoded
+        let username = self.get(&format!("{}username", prefix))?;
+        let password = base64::decode(self.get(&format!("{}_password", prefix))?).ok()?;
+        let password = String::from_utf8(password).ok()?;
+
+        Some(Auth::Basic(base64::encode(format!(
+            "{}:{}",
+            username, password
+        ))))
+    }
+}
+
+/// Whether `key` is a credential, which must never leave nana. Every
+/// per-registry setting is treated as one.
+pub fn is_auth_key(key: &str) -> bool {
+    key.starts_with("//") || AUTH_KEYS.contains(&key)
+}
+
+/// `//host[:port]/path/` form of `url` used as the key of per-registry
+/// settings, with the file name and query dropped.
+fn nerf_dart(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    let port = url
+        .port()
+        .map(|port| format!(":{}", port))
+        .unwrap_or_default();
+    let path = &url.path()[..=url.path().rfind('/')?];
+
+    Some(format!("//{}{}{}", host, port, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(settings: &[(&str, &str)]) -> Config {
+        let mut config = Config::default();
+        for (key, value) in settings {
+            config.set(key, value);
+        }
+
+        config
+    }
+
+    fn header(config: &Config, url: &str) -> Option<String> {
+        config.auth(url).map(|auth| auth.header())
+    }
+
+    #[test]
+    fn matches_host_and_port() {
+        let config = config(&[("//registry.acme.com/:_authToken", "acme")]);
+
+        assert_eq!(
+            header(&config, "https://registry.acme.com/@acme%2fui").as_deref(),
+            Some("Bearer acme")
+        );
+        assert_eq!(
+            header(&config, "https://registry.acme.com/@acme/ui/-/ui-1.0.0.tgz").as_deref(),
+            Some("Bearer acme")
+        );
+        assert_eq!(header(&config, "https://registry.acme.com:8443/a"), None);
+        assert_eq!(header(&config, "https://cdn.acme.com/a"), None);
+    }
+
+    #[test]
+    fn prefers_most_specific_path() {
+        let config = config(&[
+            ("//acme.com/:_authToken", "root"),
+            ("//acme.com/npm/private/:_authToken", "private"),
+        ]);
+
+        assert_eq!(
+            header(&config, "https://acme.com/npm/private/a").as_deref(),
+            Some("Bearer private")
+        );
+        assert_eq!(
+            header(&config, "https://acme.com/npm/public/a").as_deref(),
+            Some("Bearer root")
+        );
+    }
+
+    #[test]
+    fn builds_basic_auth() {
+        // `_password` is base64 encoded, "secret"
+        let config = config(&[
+            ("//acme.com/:username", "bob"),
+            ("//acme.com/:_password", "c2VjcmV0"),
+        ]);
+
+        assert_eq!(
+            header(&config, "https://acme.com/a").as_deref(),
+            Some(format!("Basic {}", base64::encode("bob:secret")).as_str())
+        );
+    }
+
+    #[test]
+    fn sends_unscoped_credentials_to_registry_only() {
+        let config = config(&[
+            ("registry", "https://acme.com/npm/"),
+            ("_auth", "Ym9iOnNlY3JldA=="),
+        ]);
+
+        assert_eq!(
+            header(&config, "https://acme.com/npm/a").as_deref(),
+            Some("Basic Ym9iOnNlY3JldA==")
+        );
+        assert_eq!(header(&config, "https://registry.npmjs.org/a"), None);
+    }
+
+    #[test]
+    fn detects_auth_keys() {
+        assert!(is_auth_key("_authToken"));
+        assert!(is_auth_key("//acme.com/:always-auth"));
+        assert!(!is_auth_key("registry"));
+        assert!(!is_auth_key("@acme:registry"));
+    }
+}
@@ -0,0 +1,256 @@
+use std::path::{Path, PathBuf};
+
+use console::style;
+use indexmap::IndexMap;
+
+use crate::result::NanaResult;
+
+pub mod auth;
+
+/// Config file names, in increasing precedence. `npmrc` is read for
+/// compatibility, nana's own `nanarc` overrides it.
+const CONFIG_FILES: [&str; 2] = ["npmrc", "nanarc"];
+const GLOBAL_CONFIG_DIR: &str = "/etc";
+/// Prefixes of environment variables overriding config files, e.g.
+/// `npm_config_registry` or `NANA_CONFIG_REGISTRY`.
+const ENV_PREFIXES: [&str; 2] = ["npm_config_", "nana_config_"];
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
+/// Package store location below the user's data directory.
+const STORE_DIR: &str = "nana/store";
+/// Cache location below the user's data directory.
+const CACHE_DIR: &str = "nana/cache";
+
+/// How the network is used, set with `offline` and `prefer-offline`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NetworkMode {
+    /// Cached metadata is revalidated once stale.
+    Online,
+    /// Cached metadata is used however stale, the network only on misses.
+    PreferOffline,
+    /// Only the cache and the package store are used.
+    Offline,
+}
+
+/// Settings merged from the global, user and project config files, then the
+/// environment, the latter taking precedence. Files use the `.npmrc`
+/// `key=value` format.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    values: IndexMap<String, String>,
+}
+
+impl Config {
+    pub fn load() -> NanaResult<Self> {
+        let mut config = Self::default();
+
+        for path in config_files()? {
+            config.merge_file(&path)?;
+        }
+
+        config.merge_env();
+
+        Ok(config)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|value| value.as_str())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    /// `offline` wins over `prefer-offline`, like npm.
+    pub fn network_mode(&self) -> NetworkMode {
+        if self.get("offline") == Some("true") {
+            NetworkMode::Offline
+        } else if self.get("prefer-offline") == Some("true") {
+            NetworkMode::PreferOffline
+        } else {
+            NetworkMode::Online
+        }
+    }
+
+    /// Directory of the package store shared by every project: `store-dir`,
+    /// falling back to the XDG data directory (`~/.local/share` by default).
+    pub fn store_dir(&self) -> PathBuf {
+        if let Some(dir) = self.get("store-dir") {
+            return PathBuf::from(dir);
+        }
+
+        data_dir().join(STORE_DIR)
+    }
+
+    /// Directory caching package metadata and git clones, next to the
+    /// default store so every project shares it.
+    pub fn cache_dir(&self) -> PathBuf {
+        data_dir().join(CACHE_DIR)
+    }
+
+    /// Registry serving `name`: the one configured for its scope through
+    /// `@scope:registry`, falling back to `registry`. Always ends with `/`.
+    pub fn registry(&self, name: &str) -> String {
+        let scoped = name
+            .strip_prefix('@')
+            .and_then(|name| name.split_once('/'))
+            .and_then(|(scope, _)| self.get(&format!("@{}:registry", scope)));
+
+        let registry = scoped
+            .or_else(|| self.get("registry"))
+            .unwrap_or(DEFAULT_REGISTRY);
+
+        match registry.ends_with('/') {
+            true => registry.to_string(),
+            false => format!("{}/", registry),
+        }
+    }
+
+    /// `npm_config_*` variables exposed to scripts, with characters not
+    /// allowed in variable names replaced by `_` (`@acme:registry` becomes
+    /// `npm_config__acme_registry`). Credentials are never exported.
+    pub fn npm_env(&self) -> Vec<(String, String)> {
+        self.values
+            .iter()
+            .filter(|(key, _)| !auth::is_auth_key(key))
+            .map(|(key, value)| {
+                let name: String = key
+                    .chars()
+                    .map(|c| match c.is_ascii_alphanumeric() || c == '_' {
+                        true => c,
+                        false => '_',
+                    })
+                    .collect();
+
+                (format!("npm_config_{}", name), value.clone())
+            })
+            .collect()
+    }
+
+    fn merge_file(&mut self, path: &Path) -> NanaResult<()> {
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        let content = std::fs::read_to_string(path)?;
+        for (key, value) in parse(&content) {
+            self.values.insert(interpolate(&key), interpolate(&value));
+        }
+
+        Ok(())
+    }
+
+    /// Like npm, variable names are case insensitive and `_` stands for `-`
+    /// (`npm_config_fetch_retries` sets `fetch-retries`).
+    fn merge_env(&mut self) {
+        let vars: Vec<(String, String)> = std::env::vars().collect();
+
+        for prefix in ENV_PREFIXES {
+            for (name, value) in vars.iter() {
+                let name = name.to_lowercase();
+                let key = match name.strip_prefix(prefix) {
+                    Some(key) if !key.is_empty() => key,
+                    _ => continue,
+                };
+
+                let key = match key.strip_prefix('_') {
+                    Some(rest) => format!("_{}", rest.replace('_', "-")),
+                    None => key.replace('_', "-"),
+                };
+
+                self.values.insert(key, value.clone());
+            }
+        }
+    }
+}
+
+/// Parses `.npmrc`-style content: one `key=value` per line, `#` and `;`
+/// starting comments, and optionally quoted values.
+fn parse(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+
+            (key.trim().to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Replaces `${NAME}` references with the value of environment variable
+/// `NAME`, so credentials can be kept out of config files. Unset variables
+/// are replaced by nothing with a warning, as most commands do not use the
+/// setting anyway.
+fn interpolate(raw: &str) -> String {
+    let mut result = String::new();
+    let mut rest = raw;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let name = &rest[start + 2..end];
+        let value = std::env::var(name).unwrap_or_else(|_| {
+            eprintln!(
+                "{} Environment variable '{}' used in config is not set",
+                style("WARN").yellow(),
+                name
+            );
+            String::new()
+        });
+
+        result.push_str(&rest[..start]);
+        result.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+
+    result
+}
+
+/// Global (`/etc/npmrc`), user (`~/.npmrc`) and project (`./.npmrc`)
+/// config files, in increasing precedence.
+fn config_files() -> NanaResult<Vec<PathBuf>> {
+    let global = PathBuf::from(GLOBAL_CONFIG_DIR);
+    let mut files: Vec<PathBuf> = CONFIG_FILES.iter().map(|f| global.join(f)).collect();
+
+    let mut dirs = vec![];
+    if let Some(home) = home_dir() {
+        dirs.push(home);
+    }
+
+    let cwd = std::env::current_dir()?;
+    if !dirs.contains(&cwd) {
+        dirs.push(cwd);
+    }
+
+    for dir in dirs {
+        files.extend(CONFIG_FILES.iter().map(|f| dir.join(format!(".{}", f))));
+    }
+
+    Ok(files)
+}
+
+/// XDG data directory, `~/.local/share` by default.
+fn data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_dir().map(|home| home.join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from(".nana"))
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
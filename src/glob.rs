@@ -0,0 +1,22 @@
+/// Minimal glob: `*` and `?` never match `/`, `**` matches any number of
+/// directories.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    fn go(p: &[u8], t: &[u8]) -> bool {
+        match p.first() {
+            None => t.is_empty(),
+            Some(b'*') if p.get(1) == Some(&b'*') => match p[2..].strip_prefix(b"/") {
+                Some(rest) => (0..=t.len())
+                    .filter(|&i| i == 0 || t[i - 1] == b'/')
+                    .any(|i| go(rest, &t[i..])),
+                None => (0..=t.len()).any(|i| go(&p[2..], &t[i..])),
+            },
+            Some(b'*') => (0..=t.len())
+                .take_while(|&i| i == 0 || t[i - 1] != b'/')
+                .any(|i| go(&p[1..], &t[i..])),
+            Some(b'?') => !t.is_empty() && t[0] != b'/' && go(&p[1..], &t[1..]),
+            Some(c) => t.first() == Some(c) && go(&p[1..], &t[1..]),
+        }
+    }
+
+    go(pattern.as_bytes(), text.as_bytes())
+}
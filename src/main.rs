@@ -1,8 +1,10 @@
 mod cli;
 mod commands;
+mod config;
+mod glob;
 mod package;
-mod progress;
 mod result;
+mod script;
 
 fn main() {
     cli::init();
@@ -0,0 +1,336 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Map, Serializer, Value};
+
+use crate::result::{NanaError, NanaResult};
+
+use super::{metadata::DependencyKind, package_in_dir, PACKAGE_NAME};
+
+const DEFAULT_INDENT: &str = "  ";
+
+/// Raw, editable `package.json`. Unlike `Package` it keeps every field, the
+/// original key order and indentation, so writing it back only changes what
+/// was actually edited. It is also lenient enough to read the manifests of
+/// installed packages.
+pub struct Manifest {
+    path: PathBuf,
+    raw: Map<String, Value>,
+    indent: String,
+    trailing_newline: bool,
+}
+
+impl Manifest {
+    pub fn from_local_package() -> NanaResult<Self> {
+        package_in_dir()?;
+
+        Self::from_dir(Path::new(""))
+    }
+
+    /// Reads the `package.json` of the package living in `dir`.
+    pub fn from_dir(dir: &Path) -> NanaResult<Self> {
+        let path = dir.join(PACKAGE_NAME);
+        let content = std::fs::read_to_string(&path)?;
+        let raw = match serde_json::from_str(&content)? {
+            Value::Object(raw) => raw,
+            _ => {
+                return Err(NanaError::Runtime(format!(
+                    "'{}' is not a JSON object",
+                    path.display()
+                )))
+            }
+        };
+
+        Ok(Self {
+            path,
+            raw,
+            indent: detect_indent(&content),
+            trailing_newline: content.ends_with('\n'),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.raw.get("name").and_then(|name| name.as_str())
+    }
+
+    pub fn main(&self) -> Option<&str> {
+        self.raw.get("main").and_then(|main| main.as_str())
+    }
+
+    /// Patterns of the `files` published with the package, if restricted.
+    pub fn files(&self) -> Option<Vec<String>> {
+        self.raw
+            .get("files")
+            .and_then(|files| files.as_array())
+            .map(|files| {
+                files
+                    .iter()
+                    .filter_map(|file| Some(file.as_str()?.to_string()))
+                    .collect()
+            })
+    }
+
+    /// Executables declared through `bin` (either a single path named after
+    /// the package, or a name to path map) or `directories.bin`.
+    pub fn bins(&self) -> Vec<(String, String)> {
+        match self.raw.get("bin") {
+            Some(Value::String(target)) => match self.name() {
+                Some(name) => {
+                    let name = name.rsplit('/').next().unwrap_or(name);
+                    vec![(name.to_string(), target.clone())]
+                }
+                None => vec![],
+            },
+            Some(Value::Object(bins)) => bins
+                .iter()
+                .filter_map(|(name, target)| Some((name.clone(), target.as_str()?.to_string())))
+                .collect(),
+            _ => self.directory_bins(),
+        }
+    }
+
+    /// The given top-level `fields` as `(key, value)` pairs, nested objects
+    /// and arrays flattened with `_` separated keys (`config_port`, `bin_0`).
+    pub fn flatten(&self, fields: &[&str]) -> Vec<(String, String)> {
+        let mut result = vec![];
+
+        for field in fields {
+            if let Some(value) = self.raw.get(*field) {
+                flatten_value(&sanitize_key(field), value, &mut result);
+            }
+        }
+
+        result
+    }
+
+    pub fn script(&self, name: &str) -> Option<&str> {
+        self.raw
+            .get("scripts")
+            .and_then(|scripts| scripts.get(name))
+            .and_then(|script| script.as_str())
+    }
+
+    /// Sets `name` in the section matching `kind`, dropping it from the other
+    /// dependency sections. Alphabetically sorted sections stay sorted.
+    pub fn set_dependency(&mut self, kind: DependencyKind, name: &str, version_range: &str) {
+        for other in DependencyKind::all() {
+            if other != kind {
+                self.remove_from_section(other.section(), name);
+            }
+        }
+
+        let section = self
+            .raw
+            .entry(kind.section())
+            .or_insert_with(|| Value::Object(Map::new()));
+
+        if let Value::Object(dependencies) = section {
+            let was_sorted = is_sorted(dependencies);
+
+            dependencies.insert(name.to_string(), Value::String(version_range.to_string()));
+
+            if was_sorted && !is_sorted(dependencies) {
+                let mut entries: Vec<(String, Value)> =
+                    std::mem::take(dependencies).into_iter().collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                dependencies.extend(entries);
+            }
+        }
+    }
+
+    /// Removes `name` from every dependency section, returning whether it was
+    /// declared anywhere.
+    pub fn remove_dependency(&mut self, name: &str) -> bool {
+        DependencyKind::all()
+            .iter()
+            .map(|kind| self.remove_from_section(kind.section(), name))
+            .fold(false, |removed, r| removed | r)
+    }
+
+    pub fn save(&self) -> NanaResult<()> {
+        let mut data = vec![];
+        let formatter = PrettyFormatter::with_indent(self.indent.as_bytes());
+        let mut serializer = Serializer::with_formatter(&mut data, formatter);
+        self.raw.serialize(&mut serializer)?;
+
+        if self.trailing_newline {
+            data.push(b'\n');
+        }
+
+        std::fs::write(&self.path, data)?;
+
+        Ok(())
+    }
+
+    fn directory_bins(&self) -> Vec<(String, String)> {
+        let dir = match self
+            .raw
+            .get("directories")
+            .and_then(|directories| directories.get("bin"))
+            .and_then(|dir| dir.as_str())
+        {
+            Some(dir) => dir,
+            None => return vec![],
+        };
+
+        let package_dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let entries = match std::fs::read_dir(package_dir.join(dir)) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+
+        entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                let target = format!("{}/{}", dir.trim_end_matches('/'), name);
+                (name, target)
+            })
+            .collect()
+    }
+
+    /// `Map::remove` swaps the last entry into the removed slot, the section
+    /// is rebuilt instead so the remaining keys keep their order.
+    fn remove_from_section(&mut self, section: &str, name: &str) -> bool {
+        match self.raw.get_mut(section) {
+            Some(Value::Object(dependencies)) if dependencies.contains_key(name) => {
+                *dependencies = std::mem::take(dependencies)
+                    .into_iter()
+                    .filter(|(key, _)| key != name)
+                    .collect();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn flatten_value(prefix: &str, value: &Value, result: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten_value(&format!("{}_{}", prefix, sanitize_key(key)), value, result);
+            }
+        }
+        Value::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                flatten_value(&format!("{}_{}", prefix, index), value, result);
+            }
+        }
+        Value::String(value) => result.push((prefix.to_string(), value.clone())),
+        Value::Null | Value::Bool(false) => result.push((prefix.to_string(), String::new())),
+        value => result.push((prefix.to_string(), value.to_string())),
+    }
+}
+
+fn sanitize_key(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn detect_indent(content: &str) -> String {
+    content
+        .lines()
+        .skip(1)
+        .find(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.chars()
+                .take_while(|c| c.is_whitespace())
+                .collect::<String>()
+        })
+        .filter(|indent| !indent.is_empty())
+        .unwrap_or_else(|| DEFAULT_INDENT.to_string())
+}
+
+fn is_sorted(map: &Map<String, Value>) -> bool {
+    map.keys().zip(map.keys().skip(1)).all(|(a, b)| a <= b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(content: &str) -> (tempfile::TempDir, Manifest) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(PACKAGE_NAME), content).unwrap();
+        let manifest = Manifest::from_dir(dir.path()).unwrap();
+
+        (dir, manifest)
+    }
+
+    fn saved(manifest: &Manifest) -> String {
+        manifest.save().unwrap();
+        std::fs::read_to_string(manifest.path()).unwrap()
+    }
+
+    #[test]
+    fn keeps_sorted_sections_sorted() {
+        let (_dir, mut manifest) = manifest(r#"{"dependencies":{"a":"^1.0.0","c":"^1.0.0"}}"#);
+
+        manifest.set_dependency(DependencyKind::Production, "b", "^2.0.0");
+
+        assert_eq!(
+            saved(&manifest),
+            r#"{
+  "dependencies": {
+    "a": "^1.0.0",
+    "b": "^2.0.0",
+    "c": "^1.0.0"
+  }
+}"#
+        );
+    }
+
+    #[test]
+    fn appends_to_unsorted_sections() {
+        let (_dir, mut manifest) = manifest(r#"{"dependencies":{"c":"^1.0.0","a":"^1.0.0"}}"#);
+
+        manifest.set_dependency(DependencyKind::Production, "b", "^2.0.0");
+
+        let saved = saved(&manifest);
+        let position = |name: &str| saved.find(&format!("\"{}\"", name)).unwrap();
+        assert!(position("c") < position("a"));
+        assert!(position("a") < position("b"));
+    }
+
+    #[test]
+    fn moves_dependency_between_sections() {
+        let (_dir, mut manifest) =
+            manifest(r#"{"dependencies":{"a":"^1.0.0","b":"^1.0.0"},"devDependencies":{}}"#);
+
+        manifest.set_dependency(DependencyKind::Development, "a", "^1.0.0");
+
+        assert_eq!(
+            saved(&manifest),
+            r#"{
+  "dependencies": {
+    "b": "^1.0.0"
+  },
+  "devDependencies": {
+    "a": "^1.0.0"
+  }
+}"#
+        );
+        assert!(manifest.remove_dependency("a"));
+        assert!(!manifest.remove_dependency("a"));
+    }
+
+    #[test]
+    fn preserves_key_order_and_indentation() {
+        let content = "{\n    \"version\": \"1.0.0\",\n    \"name\": \"app\",\n    \"scripts\": {\n        \"test\": \"jest\"\n    }\n}\n";
+        let (_dir, mut manifest) = manifest(content);
+
+        manifest.set_dependency(DependencyKind::Optional, "fsevents", "^2.3.0");
+
+        assert_eq!(
+            saved(&manifest),
+            "{\n    \"version\": \"1.0.0\",\n    \"name\": \"app\",\n    \"scripts\": {\n        \"test\": \"jest\"\n    },\n    \"optionalDependencies\": {\n        \"fsevents\": \"^2.3.0\"\n    }\n}\n"
+        );
+    }
+}
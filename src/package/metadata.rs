@@ -2,35 +2,159 @@ use std::collections::HashMap;
 
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    commands::install::specifier,
+    result::{NanaError, NanaResult},
+};
 
 pub type Dependencies = IndexMap<String, String>;
 
+/// Version of packages whose manifest has none, like npm.
+pub const DEFAULT_VERSION: &str = "0.0.0";
+
 // source: https://github.com/npm/registry/blob/master/docs/responses/package-metadata.md#dist
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Dist {
     pub tarball: String,
     pub shasum: Option<String>,
     pub integrity: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Which `package.json` section a package is (transitively) required from,
+/// ordered from the strongest to the weakest.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+    #[default]
+    Production,
+    Optional,
+    Development,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct MetadataVersion {
     pub name: String,
     pub version: String,
     pub dist: Dist,
     pub dependencies: Option<Dependencies>,
+    #[serde(
+        rename = "optionalDependencies",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub optional_dependencies: Option<Dependencies>,
+    /// Only kept for workspaces, whose dev dependencies are installed too.
+    #[serde(
+        rename = "devDependencies",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub dev_dependencies: Option<Dependencies>,
+    /// Only set on lock entries, never sent by the registry.
+    #[serde(default, skip_serializing_if = "DependencyKind::is_production")]
+    pub kind: DependencyKind,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Metadata {
-    pub name: String,
-    pub modified: String,
+    #[serde(rename = "dist-tags", default)]
+    pub dist_tags: HashMap<String, String>,
     pub versions: HashMap<String, MetadataVersion>,
 }
 
 impl MetadataVersion {
+    /// Builds the locked version of a package from the raw content of its own
+    /// `package.json` rather than from the registry, installed as `name`.
+    pub fn from_manifest(name: &str, content: &[u8], dist: Dist) -> NanaResult<Self> {
+        let mut manifest: Map<String, Value> = match serde_json::from_slice(content)? {
+            Value::Object(manifest) => manifest,
+            _ => {
+                return Err(NanaError::Runtime(format!(
+                    "'package.json' of '{}' is not a JSON object",
+                    name
+                )))
+            }
+        };
+
+        manifest.remove("devDependencies");
+        manifest.insert("name".to_string(), json!(name));
+        manifest
+            .entry("version")
+            .or_insert_with(|| json!(DEFAULT_VERSION));
+        manifest.insert("dist".to_string(), serde_json::to_value(dist)?);
+
+        Ok(serde_json::from_value(Value::Object(manifest))?)
+    }
+
     pub fn key(&self) -> String {
         format!("{}@{}", self.name, self.version)
     }
+
+    /// Makes local dependencies declared by the package in `dir` relative to
+    /// the project root.
+    pub fn rebase_dependencies(&mut self, dir: &str) {
+        for dependencies in [
+            &mut self.dependencies,
+            &mut self.optional_dependencies,
+            &mut self.dev_dependencies,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for spec in dependencies.values_mut() {
+                *spec = specifier::rebase(spec, dir);
+            }
+        }
+    }
+
+    /// Regular, optional and dev dependencies, each with the kind of its
+    /// section. Registries repeat optional dependencies in `dependencies`,
+    /// those entries are only returned once, as optional.
+    pub fn all_dependencies(&self) -> Vec<(String, String, DependencyKind)> {
+        let optional = self.optional_dependencies.clone().unwrap_or_default();
+        let regular = self.dependencies.clone().unwrap_or_default();
+        let dev = self.dev_dependencies.clone().unwrap_or_default();
+
+        let mut result: Vec<(String, String, DependencyKind)> = regular
+            .into_iter()
+            .filter(|(k, _)| !optional.contains_key(k))
+            .map(|(k, v)| (k, v, DependencyKind::Production))
+            .collect();
+
+        result.extend(
+            optional
+                .into_iter()
+                .map(|(k, v)| (k, v, DependencyKind::Optional)),
+        );
+
+        for (name, version_range) in dev {
+            if !result.iter().any(|(n, _, _)| n.eq(&name)) {
+                result.push((name, version_range, DependencyKind::Development));
+            }
+        }
+
+        result
+    }
+}
+
+impl DependencyKind {
+    pub fn all() -> [Self; 3] {
+        [Self::Production, Self::Optional, Self::Development]
+    }
+
+    pub fn is_production(&self) -> bool {
+        *self == Self::Production
+    }
+
+    /// `package.json` field declaring dependencies of this kind.
+    pub fn section(&self) -> &'static str {
+        match self {
+            Self::Production => "dependencies",
+            Self::Optional => "optionalDependencies",
+            Self::Development => "devDependencies",
+        }
+    }
 }
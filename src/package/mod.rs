@@ -1,41 +1,116 @@
+pub mod manifest;
 pub mod metadata;
+pub mod workspace;
 
-use std::path::Path;
+use std::{path::Path, sync::OnceLock};
 
-use package_json_schema::PackageJson;
+use indexmap::IndexMap;
+use package_json_schema::{PackageJson, Workspaces};
 use validator::Validate;
 
 use crate::result::{NanaError, NanaResult, PackageError};
 
-const PACKAGE_NAME: &str = "package.json";
+use self::{metadata::DependencyKind, workspace::Workspace};
+
+pub const PACKAGE_NAME: &str = "package.json";
+
+/// Specifier of workspaces the project root depends on implicitly.
+const ANY_WORKSPACE: &str = "workspace:*";
 
 pub struct Package {
     package: PackageJson,
+    /// Discovered on first use, as only some commands need them.
+    workspaces: OnceLock<Vec<Workspace>>,
 }
 
 impl Package {
+    /// Loads the project's `package.json`. The workspaces it declares are
+    /// discovered when first needed.
     pub fn from_local_package() -> NanaResult<Self> {
-        match package_in_dir() {
-            Ok(_) => {
-                let raw = std::fs::read_to_string(PACKAGE_NAME)?;
-                let package = PackageJson::try_from(raw)?;
+        package_in_dir()?;
 
-                package.validate()?;
+        let mut package = Self::from_dir(Path::new(""))?;
+        package.workspaces = OnceLock::new();
 
-                Ok(Package { package })
-            }
-            Err(error) => Err(error),
+        Ok(package)
+    }
+
+    /// Loads and validates the `package.json` found in `dir`, ignoring its
+    /// workspaces.
+    pub fn from_dir(dir: &Path) -> NanaResult<Self> {
+        let raw = std::fs::read_to_string(dir.join(PACKAGE_NAME))?;
+        let package = PackageJson::try_from(raw)?;
+
+        package.validate()?;
+
+        Ok(Package {
+            package,
+            workspaces: OnceLock::from(vec![]),
+        })
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.package.name.as_deref()
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.package.version.as_deref()
+    }
+
+    pub fn workspaces(&self) -> NanaResult<&[Workspace]> {
+        if let Some(workspaces) = self.workspaces.get() {
+            return Ok(workspaces);
         }
+
+        let workspaces = workspace::discover(&self.workspace_patterns())?;
+
+        Ok(self.workspaces.get_or_init(|| workspaces))
     }
 
     pub fn dependencies(&self) -> Vec<(String, String)> {
-        match &self.package.dependencies {
-            Some(dependencies) => dependencies
-                .iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect(),
-            None => vec![],
+        section(&self.package.dependencies)
+    }
+
+    pub fn dev_dependencies(&self) -> Vec<(String, String)> {
+        section(&self.package.dev_dependencies)
+    }
+
+    pub fn optional_dependencies(&self) -> Vec<(String, String)> {
+        section(&self.package.optional_dependencies)
+    }
+
+    /// Dependencies of every section, each name only once. A package listed in
+    /// several sections takes the kind of the first one among `dependencies`,
+    /// `optionalDependencies` and `devDependencies`. Workspaces come last, as
+    /// production dependencies unless already declared.
+    pub fn all_dependencies(&self) -> NanaResult<Vec<(String, String, DependencyKind)>> {
+        let mut result: Vec<(String, String, DependencyKind)> = vec![];
+
+        let sections = [
+            (self.dependencies(), DependencyKind::Production),
+            (self.optional_dependencies(), DependencyKind::Optional),
+            (self.dev_dependencies(), DependencyKind::Development),
+        ];
+
+        for (dependencies, kind) in sections {
+            for (name, version_range) in dependencies {
+                if !result.iter().any(|(n, _, _)| n.eq(&name)) {
+                    result.push((name, version_range, kind));
+                }
+            }
+        }
+
+        for workspace in self.workspaces()? {
+            if !result.iter().any(|(n, _, _)| n.eq(&workspace.name)) {
+                result.push((
+                    workspace.name.clone(),
+                    ANY_WORKSPACE.to_string(),
+                    DependencyKind::Production,
+                ));
+            }
         }
+
+        Ok(result)
     }
 
     pub fn script(&self, name: &str) -> Option<String> {
@@ -48,14 +123,33 @@ impl Package {
         }
     }
 
-    pub fn has_dependencies(&self) -> bool {
-        match &self.package.dependencies {
-            Some(dependencies) => !dependencies.is_empty(),
-            None => false,
+    pub fn has_dependencies(&self) -> NanaResult<bool> {
+        Ok(!self.all_dependencies()?.is_empty())
+    }
+
+    /// Globs of the `workspaces` field, in either its array or object form.
+    fn workspace_patterns(&self) -> Vec<String> {
+        match &self.package.workspaces {
+            Some(Workspaces::List(patterns)) => patterns.clone(),
+            Some(Workspaces::Object {
+                packages: Some(patterns),
+                ..
+            }) => patterns.clone(),
+            _ => vec![],
         }
     }
 }
 
+fn section(dependencies: &Option<IndexMap<String, String>>) -> Vec<(String, String)> {
+    match dependencies {
+        Some(dependencies) => dependencies
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect(),
+        None => vec![],
+    }
+}
+
 fn package_in_dir() -> NanaResult<()> {
     match Path::new(PACKAGE_NAME).exists() {
         true => Ok(()),
@@ -0,0 +1,177 @@
+use std::path::Path;
+
+use indexmap::IndexMap;
+
+use crate::{
+    commands::install::specifier::WORKSPACE_PREFIX,
+    glob,
+    result::{NanaError, NanaResult},
+};
+
+use super::{
+    metadata::{Dependencies, Dist, MetadataVersion, DEFAULT_VERSION},
+    Package, PACKAGE_NAME,
+};
+
+/// Directories never searched for workspaces, besides hidden ones.
+const IGNORED_DIRS: [&str; 1] = ["node_modules"];
+
+/// Package of a monorepo living in a subdirectory of the project root,
+/// symlinked into the root `node_modules` rather than fetched.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub name: String,
+    /// Directory of the workspace, relative to the project root.
+    pub path: String,
+    /// How the workspace is locked: every dependency section of its
+    /// `package.json`, and its directory as `dist.tarball`.
+    pub meta_version: MetadataVersion,
+}
+
+impl Workspace {
+    fn from_dir(path: &str) -> NanaResult<Self> {
+        let package = Package::from_dir(Path::new(path))?;
+        let name = match package.name() {
+            Some(name) => name.to_string(),
+            None => {
+                return Err(NanaError::Runtime(format!(
+                    "Workspace '{}' has no name",
+                    path
+                )))
+            }
+        };
+
+        let mut meta_version = MetadataVersion {
+            name: name.clone(),
+            version: package.version().unwrap_or(DEFAULT_VERSION).to_string(),
+            dist: Dist {
+                tarball: format!("{}{}", WORKSPACE_PREFIX, path),
+                shasum: None,
+                integrity: None,
+            },
+            dependencies: section(package.dependencies()),
+            optional_dependencies: section(package.optional_dependencies()),
+            dev_dependencies: section(package.dev_dependencies()),
+            kind: Default::default(),
+        };
+        meta_version.rebase_dependencies(path);
+
+        Ok(Self {
+            name,
+            path: path.to_string(),
+            meta_version,
+        })
+    }
+
+    /// Whether `locked` is still this workspace, whatever its kind.
+    pub fn matches(&self, locked: &MetadataVersion) -> bool {
+        let meta_version = MetadataVersion {
+            kind: locked.kind,
+            ..self.meta_version.clone()
+        };
+
+        meta_version == *locked
+    }
+}
+
+/// Finds the workspaces matched by `patterns`: directories holding a
+/// `package.json`, relative to the project root. Patterns starting with `!`
+/// exclude directories, and the last matching pattern wins.
+pub fn discover(patterns: &[String]) -> NanaResult<Vec<Workspace>> {
+    let rules: Vec<(bool, String)> = patterns
+        .iter()
+        .map(|pattern| match pattern.strip_prefix('!') {
+            Some(pattern) => (true, normalize(pattern)),
+            None => (false, normalize(pattern)),
+        })
+        .collect();
+
+    if rules.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // Only `**` reaches deeper than the pattern itself
+    let max_depth = match rules.iter().any(|(_, pattern)| pattern.contains("**")) {
+        true => usize::MAX,
+        false => rules
+            .iter()
+            .map(|(_, pattern)| pattern.split('/').count())
+            .max()
+            .unwrap_or(0),
+    };
+
+    let mut dirs = vec![];
+    walk("", max_depth, &mut dirs)?;
+
+    let mut workspaces: IndexMap<String, Workspace> = IndexMap::new();
+    for dir in dirs {
+        let is_included = rules
+            .iter()
+            .rev()
+            .find(|(_, pattern)| glob::matches(pattern, &dir))
+            .is_some_and(|(negated, _)| !negated);
+
+        if !is_included || !Path::new(&dir).join(PACKAGE_NAME).exists() {
+            continue;
+        }
+
+        let workspace = Workspace::from_dir(&dir)?;
+        if let Some(other) = workspaces.get(&workspace.name) {
+            return Err(NanaError::Runtime(format!(
+                "Workspaces '{}' and '{}' are both named '{}'",
+                other.path, workspace.path, workspace.name
+            )));
+        }
+
+        workspaces.insert(workspace.name.clone(), workspace);
+    }
+
+    Ok(workspaces.into_values().collect())
+}
+
+/// Every directory below `prefix`, down to `depth` levels, sorted.
+fn walk(prefix: &str, depth: usize, dirs: &mut Vec<String>) -> NanaResult<()> {
+    if depth == 0 {
+        return Ok(());
+    }
+
+    let dir = match prefix.is_empty() {
+        true => Path::new("."),
+        false => Path::new(prefix),
+    };
+
+    let mut entries: Vec<String> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.') && !IGNORED_DIRS.contains(&name.as_str()))
+        .collect();
+    entries.sort();
+
+    for name in entries {
+        let path = match prefix.is_empty() {
+            true => name,
+            false => format!("{}/{}", prefix, name),
+        };
+
+        dirs.push(path.clone());
+        walk(&path, depth - 1, dirs)?;
+    }
+
+    Ok(())
+}
+
+fn section(dependencies: Vec<(String, String)>) -> Option<Dependencies> {
+    match dependencies.is_empty() {
+        true => None,
+        false => Some(dependencies.into_iter().collect()),
+    }
+}
+
+fn normalize(pattern: &str) -> String {
+    pattern
+        .trim()
+        .trim_start_matches("./")
+        .trim_end_matches('/')
+        .to_string()
+}
@@ -15,18 +15,38 @@ pub enum NanaError {
 
 #[derive(Debug, Clone)]
 pub enum PackageError {
+    DependencyNotFound(String),
+    IntegrityMismatch(String, String, String),
+    LifecycleFailed(String, String, String),
+    MissingIntegrity(String),
     Invalid(validator::ValidationErrors),
+    NotAvailableOffline(String),
     NotFound,
     ScriptNotFound(String),
+    ScriptFailed(String, i32),
+    VersionNotFound(String, String),
+    WorkspaceNotFound(String),
 }
 
 #[derive(Debug, Clone)]
 pub enum LockError {
     NotFound,
+    OutOfDate,
 }
 
 impl std::error::Error for NanaError {}
 
+impl NanaError {
+    /// Code nana exits with when halted by this error. Failing scripts
+    /// forward their own exit code.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Package(PackageError::ScriptFailed(_, code)) => *code,
+            _ => 1,
+        }
+    }
+}
+
 impl Display for NanaError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -43,8 +63,40 @@ impl Display for PackageError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NotFound => write!(f, "Package not found"),
+            Self::DependencyNotFound(name) => {
+                write!(f, "'{}' is not a dependency of this package", name)
+            }
             Self::ScriptNotFound(name) => write!(f, "Could not find script '{}' in package", name),
+            Self::ScriptFailed(name, code) => {
+                write!(f, "Script '{}' failed with exit code {}", name, code)
+            }
+            Self::VersionNotFound(name, range) => {
+                write!(f, "No version of '{}' matches '{}'", name, range)
+            }
+            Self::WorkspaceNotFound(name) => write!(f, "No workspace named '{}'", name),
+            Self::NotAvailableOffline(name) => {
+                write!(
+                    f,
+                    "'{}' is not available offline, it was never downloaded",
+                    name
+                )
+            }
             Self::Invalid(e) => write!(f, "Package is in an invalid format. Errors: {}", e),
+            Self::LifecycleFailed(name, event, output) => write!(
+                f,
+                "Script '{}' of '{}' failed. Output:\n{}",
+                event, name, output
+            ),
+            Self::MissingIntegrity(name) => write!(
+                f,
+                "'{}' has neither an integrity nor a shasum to be verified against",
+                name
+            ),
+            Self::IntegrityMismatch(name, expected, actual) => write!(
+                f,
+                "Integrity check failed for '{}'. Expected: {}, actual: {}",
+                name, expected, actual
+            ),
         }
     }
 }
@@ -52,7 +104,11 @@ impl Display for PackageError {
 impl Display for LockError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::NotFound => write!(f, "Lock file  not found"),
+            Self::NotFound => write!(f, "Lock file not found"),
+            Self::OutOfDate => write!(
+                f,
+                "Lock file is out of date with package.json, run `nana install` to update it"
+            ),
         }
     }
 }
@@ -81,6 +137,12 @@ impl From<std::io::Error> for NanaError {
     }
 }
 
+impl From<std::env::JoinPathsError> for NanaError {
+    fn from(e: std::env::JoinPathsError) -> Self {
+        Self::Runtime(e.to_string())
+    }
+}
+
 impl From<std::path::StripPrefixError> for NanaError {
     fn from(e: std::path::StripPrefixError) -> Self {
         Self::Runtime(e.to_string())
@@ -135,12 +197,24 @@ impl From<std::str::Utf8Error> for NanaError {
     }
 }
 
+impl From<ssri::Error> for NanaError {
+    fn from(e: ssri::Error) -> Self {
+        Self::Runtime(e.to_string())
+    }
+}
+
 impl From<tokio::task::JoinError> for NanaError {
     fn from(e: tokio::task::JoinError) -> Self {
         Self::Runtime(e.to_string())
     }
 }
 
+impl From<tokio::sync::AcquireError> for NanaError {
+    fn from(e: tokio::sync::AcquireError) -> Self {
+        Self::Runtime(e.to_string())
+    }
+}
+
 impl From<tokio::sync::mpsc::error::SendError<InstallCommand>> for NanaError {
     fn from(e: tokio::sync::mpsc::error::SendError<InstallCommand>) -> Self {
         Self::Runtime(e.to_string())
@@ -0,0 +1,131 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use crate::{config::Config, package::manifest::Manifest, result::NanaResult};
+
+#[cfg(not(windows))]
+const SHELL: (&str, &str) = ("sh", "-c");
+#[cfg(windows)]
+const SHELL: (&str, &str) = ("cmd", "/C");
+
+const BIN_DIR: &str = "node_modules/.bin";
+/// `package.json` fields exported as `npm_package_*`, the same as npm's.
+const PACKAGE_ENV_FIELDS: [&str; 5] = ["name", "version", "config", "engines", "bin"];
+
+/// Builds a command running `script` through the system shell from `cwd`,
+/// with the `node_modules/.bin` of `cwd` and of all its ancestors prepended
+/// to `PATH`, closest first.
+pub fn command(script: &str, cwd: &Path) -> NanaResult<Command> {
+    let (shell, flag) = SHELL;
+
+    let mut cmd = Command::new(shell);
+    cmd.arg(flag)
+        .arg(script)
+        .current_dir(cwd)
+        .env("PATH", search_path(cwd)?);
+
+    Ok(cmd)
+}
+
+/// npm-compatible environment for running the `event` script of the package
+/// described by `manifest`: lifecycle details, npm's selection of
+/// `package.json` fields as `npm_package_*` and every config setting as
+/// `npm_config_*`.
+pub fn environment(
+    manifest: &Manifest,
+    config: &Config,
+    event: &str,
+    script: &str,
+) -> NanaResult<Vec<(String, String)>> {
+    let init_cwd = match std::env::var("INIT_CWD") {
+        Ok(init_cwd) => init_cwd,
+        Err(_) => std::env::current_dir()?.to_string_lossy().to_string(),
+    };
+
+    let mut env = config.npm_env();
+
+    env.extend(
+        manifest
+            .flatten(&PACKAGE_ENV_FIELDS)
+            .into_iter()
+            .map(|(key, value)| (format!("npm_package_{}", key), value)),
+    );
+
+    env.extend([
+        ("npm_lifecycle_event".to_string(), event.to_string()),
+        ("npm_lifecycle_script".to_string(), script.to_string()),
+        (
+            "npm_package_json".to_string(),
+            absolute(manifest.path())?.to_string_lossy().to_string(),
+        ),
+        (
+            "npm_execpath".to_string(),
+            std::env::current_exe()?.to_string_lossy().to_string(),
+        ),
+        (
+            "npm_config_user_agent".to_string(),
+            format!(
+                "{}/{} {} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            ),
+        ),
+        ("INIT_CWD".to_string(), init_cwd),
+    ]);
+
+    Ok(env)
+}
+
+/// Appends `args` to `script`, quoted so the shell passes each of them
+/// through as a single, literal argument.
+pub fn with_args(script: &str, args: &[String]) -> String {
+    args.iter().fold(script.to_string(), |script, arg| {
+        format!("{} {}", script, quote(arg))
+    })
+}
+
+fn quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c);
+
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Exit code to report for a finished script. Like shells do, a process
+/// killed by a signal maps to `128 + signal`.
+pub fn exit_code(status: &ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+fn search_path(cwd: &Path) -> NanaResult<std::ffi::OsString> {
+    let mut paths: Vec<PathBuf> = absolute(cwd)?
+        .ancestors()
+        .map(|dir| dir.join(BIN_DIR))
+        .collect();
+
+    if let Some(path) = std::env::var_os("PATH") {
+        paths.extend(std::env::split_paths(&path));
+    }
+
+    Ok(std::env::join_paths(paths)?)
+}
+
+fn absolute(path: &Path) -> NanaResult<PathBuf> {
+    Ok(std::env::current_dir()?.join(path))
+}
@@ -0,0 +1,49 @@
+mod common;
+mod registry;
+
+use registry::Registry;
+
+#[test]
+fn saves_caret_range_on_best_version() {
+    let registry = Registry::new();
+    for version in ["1.0.0", "1.2.0", "2.0.0"] {
+        registry.publish(
+            &format!(r#"{{"name":"lib","version":"{}"}}"#, version),
+            &[("index.js", version)],
+        );
+    }
+    registry.publish(r#"{"name":"tool","version":"3.1.0"}"#, &[]);
+
+    let project = common::project("{\n    \"name\": \"app\",\n    \"version\": \"1.0.0\"\n}\n");
+
+    let output = common::nana(project.path())
+        .env("NPM_CONFIG_REGISTRY", registry.url())
+        .args(["add", "lib@^1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = common::nana(project.path())
+        .env("NPM_CONFIG_REGISTRY", registry.url())
+        .args(["add", "-D", "--exact", "tool"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let manifest = std::fs::read_to_string(project.path().join("package.json")).unwrap();
+    assert_eq!(
+        manifest,
+        "{\n    \"name\": \"app\",\n    \"version\": \"1.0.0\",\n    \"dependencies\": {\n        \"lib\": \"^1.2.0\"\n    },\n    \"devDependencies\": {\n        \"tool\": \"3.1.0\"\n    }\n}\n"
+    );
+
+    let installed = std::fs::read_to_string(project.path().join("node_modules/lib/index.js"));
+    assert_eq!(installed.unwrap(), "1.2.0");
+    assert!(project
+        .path()
+        .join("node_modules/tool/package.json")
+        .exists());
+
+    let lock = std::fs::read_to_string(project.path().join("nana.lock.yml")).unwrap();
+    assert!(lock.contains("lib: ^1.2.0"), "{}", lock);
+    assert!(lock.contains("tool: 3.1.0"), "{}", lock);
+}
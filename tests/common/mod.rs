@@ -0,0 +1,23 @@
+use std::{path::Path, process::Command};
+
+use tempfile::TempDir;
+
+/// Project directory holding `manifest` as its `package.json`.
+pub fn project(manifest: &str) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("package.json"), manifest).unwrap();
+
+    dir
+}
+
+/// nana running in `dir`, kept away from the user's config, store and the
+/// network.
+pub fn nana(dir: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_nana"));
+    cmd.current_dir(dir)
+        .env("HOME", dir)
+        .env("XDG_DATA_HOME", dir.join(".data"))
+        .env("NPM_CONFIG_REGISTRY", "http://127.0.0.1:9/");
+
+    cmd
+}
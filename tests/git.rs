@@ -0,0 +1,140 @@
+mod common;
+
+use git2::{build::RepoBuilder, IndexAddOption, Oid, Repository, Signature};
+use tempfile::TempDir;
+
+/// Writes `files` in the work tree of `repo`, commits them and tags the
+/// commit.
+fn release(repo: &Repository, tag: &str, files: &[(&str, &str)]) -> Oid {
+    let dir = repo.workdir().unwrap();
+    for (path, content) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    let mut index = repo.index().unwrap();
+    index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+    let signature = Signature::now("nana", "nana@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    let oid = repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            tag,
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+
+    let object = repo.find_object(oid, None).unwrap();
+    repo.tag_lightweight(tag, &object, false).unwrap();
+
+    oid
+}
+
+fn manifest(version: &str) -> String {
+    format!(
+        r#"{{"name":"pkg","version":"{}","main":"lib/index.js","files":["lib"]}}"#,
+        version
+    )
+}
+
+#[test]
+fn installs_semver_tag_from_bare_repository() {
+    let origin = TempDir::new().unwrap();
+    let work = Repository::init(origin.path().join("work")).unwrap();
+
+    let files = [
+        ("lib/index.js", "module.exports = 1"),
+        ("test/index.js", ""),
+        (".npmignore", "lib"),
+    ];
+    release(
+        &work,
+        "v1.0.0",
+        &[&files[..], &[("package.json", &manifest("1.0.0"))]].concat(),
+    );
+    let expected = release(&work, "v1.1.0", &[("package.json", &manifest("1.1.0"))]);
+    release(&work, "v2.0.0", &[("package.json", &manifest("2.0.0"))]);
+
+    let bare = origin.path().join("pkg.git");
+    RepoBuilder::new()
+        .bare(true)
+        .clone(
+            &format!("file://{}", origin.path().join("work").display()),
+            &bare,
+        )
+        .unwrap();
+
+    let url = format!("git+file://{}", bare.display());
+    let project = common::project(&format!(
+        r#"{{"name":"app","version":"1.0.0","dependencies":{{"pkg":"{}#semver:^1"}}}}"#,
+        url
+    ));
+
+    let output = common::nana(project.path())
+        .arg("install")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    // The range is pinned to the commit of the highest matching tag
+    let lock = std::fs::read_to_string(project.path().join("nana.lock.yml")).unwrap();
+    assert!(lock.contains(&format!("{}#{}", url, expected)), "{}", lock);
+
+    // Packed like `npm pack`: only `files`, whatever `.npmignore` says
+    let package = project.path().join("node_modules/pkg");
+    let installed = std::fs::read_to_string(package.join("package.json")).unwrap();
+    assert!(installed.contains(r#""version":"1.1.0""#));
+    assert!(package.join("lib/index.js").is_file());
+    assert!(!package.join("test").exists());
+    assert!(!package.join(".npmignore").exists());
+}
+
+#[test]
+fn reinstalls_dependency_moved_to_another_commit() {
+    let origin = TempDir::new().unwrap();
+    let work = Repository::init(origin.path()).unwrap();
+
+    // Both commits have the same version
+    let manifest = manifest("1.0.0");
+    for (tag, content) in [
+        ("first", "module.exports = 1"),
+        ("second", "module.exports = 2"),
+    ] {
+        release(
+            &work,
+            tag,
+            &[("package.json", &manifest), ("lib/index.js", content)],
+        );
+    }
+
+    let url = format!("git+file://{}", origin.path().display());
+    let project = common::project("{}");
+    let installed = |tag: &str| {
+        std::fs::write(
+            project.path().join("package.json"),
+            format!(
+                r#"{{"name":"app","version":"1.0.0","dependencies":{{"pkg":"{}#{}"}}}}"#,
+                url, tag
+            ),
+        )
+        .unwrap();
+
+        let output = common::nana(project.path())
+            .arg("install")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+
+        std::fs::read_to_string(project.path().join("node_modules/pkg/lib/index.js")).unwrap()
+    };
+
+    assert_eq!(installed("first"), "module.exports = 1");
+    assert_eq!(installed("second"), "module.exports = 2");
+}
@@ -0,0 +1,223 @@
+mod common;
+mod registry;
+
+use registry::Registry;
+
+#[test]
+fn reports_failed_root_script() {
+    let project = common::project(
+        r#"{
+            "name": "app",
+            "version": "1.0.0",
+            "scripts": { "preinstall": "echo broken && exit 4" }
+        }"#,
+    );
+
+    let output = common::nana(project.path())
+        .arg("install")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.lines().any(|line| line == "broken"), "{}", stdout);
+    assert!(
+        stdout.contains("Script 'preinstall' failed with exit code 4"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn reinstalls_dependency_switched_to_another_source() {
+    let project = common::project("{}");
+    for (dir, content) in [("one", "module.exports = 1"), ("two", "module.exports = 2")] {
+        let dir = project.path().join("vendor").join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("package.json"),
+            r#"{"name":"dep","version":"1.0.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("index.js"), content).unwrap();
+    }
+
+    let installed = |source: &str| {
+        std::fs::write(
+            project.path().join("package.json"),
+            format!(
+                r#"{{"name":"app","version":"1.0.0","dependencies":{{"dep":"{}"}}}}"#,
+                source
+            ),
+        )
+        .unwrap();
+
+        let output = common::nana(project.path())
+            .arg("install")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+
+        std::fs::read_to_string(project.path().join("node_modules/dep/index.js")).unwrap()
+    };
+
+    // Same version each time, only the source changes
+    assert_eq!(installed("file:./vendor/one"), "module.exports = 1");
+    assert_eq!(installed("file:./vendor/two"), "module.exports = 2");
+    assert_eq!(installed("link:./vendor/one"), "module.exports = 1");
+    assert_eq!(installed("link:./vendor/two"), "module.exports = 2");
+    assert_eq!(installed("file:./vendor/one"), "module.exports = 1");
+}
+
+#[test]
+fn reinstalls_alias_switched_to_another_package() {
+    let registry = Registry::new();
+    registry.publish(
+        r#"{"name":"bar","version":"1.0.0"}"#,
+        &[("index.js", "bar")],
+    );
+    registry.publish(
+        r#"{"name":"baz","version":"1.0.0"}"#,
+        &[("index.js", "baz")],
+    );
+
+    let project = common::project("{}");
+    let installed = |alias: &str| {
+        std::fs::write(
+            project.path().join("package.json"),
+            format!(
+                r#"{{"name":"app","version":"1.0.0","dependencies":{{"dep":"{}"}}}}"#,
+                alias
+            ),
+        )
+        .unwrap();
+
+        let output = common::nana(project.path())
+            .env("NPM_CONFIG_REGISTRY", registry.url())
+            .arg("install")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+
+        std::fs::read_to_string(project.path().join("node_modules/dep/index.js")).unwrap()
+    };
+
+    assert_eq!(installed("npm:bar@1.0.0"), "bar");
+    assert_eq!(installed("npm:baz@1.0.0"), "baz");
+}
+
+#[test]
+fn fails_when_optional_and_required_share_unresolvable_dependency() {
+    let project = common::project(
+        r#"{
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": { "missing": "^1.0.0" },
+            "optionalDependencies": { "opt": "file:./opt" }
+        }"#,
+    );
+    std::fs::create_dir(project.path().join("opt")).unwrap();
+    std::fs::write(
+        project.path().join("opt/package.json"),
+        r#"{"name":"opt","version":"1.0.0","dependencies":{"missing":"^1.0.0"}}"#,
+    )
+    .unwrap();
+
+    // Skipping the optional request must not end resolution while the
+    // required one is still failing
+    let output = common::nana(project.path())
+        .arg("install")
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(!project.path().join("nana.lock.yml").exists());
+    assert!(!project.path().join("node_modules").exists());
+}
+
+#[test]
+fn frozen_lockfile_refuses_outdated_lock() {
+    let registry = Registry::new();
+    for version in ["1.0.0", "2.0.0"] {
+        registry.publish(
+            &format!(r#"{{"name":"lib","version":"{}"}}"#, version),
+            &[("index.js", version)],
+        );
+    }
+
+    let project =
+        common::project(r#"{"name":"app","version":"1.0.0","dependencies":{"lib":"^1.0.0"}}"#);
+    let output = common::nana(project.path())
+        .env("NPM_CONFIG_REGISTRY", registry.url())
+        .arg("install")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let lock = std::fs::read_to_string(project.path().join("nana.lock.yml")).unwrap();
+    std::fs::write(
+        project.path().join("package.json"),
+        r#"{"name":"app","version":"1.0.0","dependencies":{"lib":"^2.0.0"}}"#,
+    )
+    .unwrap();
+
+    for args in [&["install", "--frozen-lockfile"][..], &["ci"]] {
+        let output = common::nana(project.path())
+            .env("NPM_CONFIG_REGISTRY", registry.url())
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{:?}", output);
+        let printed = [output.stdout, output.stderr].concat();
+        assert!(
+            String::from_utf8_lossy(&printed).contains("Lock file is out of date"),
+            "{}",
+            String::from_utf8_lossy(&printed)
+        );
+
+        let locked = std::fs::read_to_string(project.path().join("nana.lock.yml")).unwrap();
+        assert_eq!(locked, lock);
+        let installed =
+            std::fs::read_to_string(project.path().join("node_modules/lib/index.js")).unwrap();
+        assert_eq!(installed, "1.0.0");
+    }
+}
+
+#[test]
+fn offline_install_uses_cached_metadata_only() {
+    let registry = Registry::new();
+    registry.publish(
+        r#"{"name":"lib","version":"1.0.0"}"#,
+        &[("index.js", "1.0.0")],
+    );
+
+    let project =
+        common::project(r#"{"name":"app","version":"1.0.0","dependencies":{"lib":"^1.0.0"}}"#);
+    let install = |offline: bool| {
+        let mut command = common::nana(project.path());
+        command
+            .env("NPM_CONFIG_REGISTRY", registry.url())
+            .arg("install");
+        if offline {
+            command.arg("--offline");
+        }
+        command.output().unwrap()
+    };
+
+    // Reachable, the registry is still not asked for what is not cached
+    let output = install(true);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("'lib' is not available offline"),
+        "{:?}",
+        output
+    );
+    assert!(!project.path().join("nana.lock.yml").exists());
+
+    assert!(install(false).status.success());
+    std::fs::remove_dir_all(project.path().join("node_modules")).unwrap();
+    std::fs::remove_file(project.path().join("nana.lock.yml")).unwrap();
+
+    let output = install(true);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(project.path().join("node_modules/lib/index.js").is_file());
+}
@@ -0,0 +1,146 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use serde_json::{json, Value};
+use ssri::{Algorithm, IntegrityOpts};
+use tempfile::TempDir;
+
+/// npm registry serving the packages published to it, on a local port for
+/// as long as the test runs.
+pub struct Registry {
+    dir: TempDir,
+    url: String,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let root = dir.path().to_path_buf();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let path = request.split(' ').nth(1).unwrap_or("/");
+
+                let response = match std::fs::read(file_path(&root, path)) {
+                    Ok(body) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend(body);
+                        response
+                    }
+                    Err(_) => {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+
+        Self { dir, url }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Publishes a package made of `manifest` as its `package.json` and
+    /// `files`. The highest version published is tagged `latest`.
+    pub fn publish(&self, manifest: &str, files: &[(&str, &str)]) {
+        let package: Value = serde_json::from_str(manifest).unwrap();
+        let name = package["name"].as_str().unwrap();
+        let version = package["version"].as_str().unwrap();
+
+        let mut tarball = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for (path, content) in [&[("package.json", manifest)], files].concat() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tarball
+                .append_data(&mut header, format!("package/{}", path), content.as_bytes())
+                .unwrap();
+        }
+        let tarball = tarball.into_inner().unwrap().finish().unwrap();
+
+        let file_name = format!("{}-{}.tgz", name.replace('/', "-"), version);
+        std::fs::create_dir_all(self.dir.path().join("tarballs")).unwrap();
+        std::fs::write(self.dir.path().join("tarballs").join(&file_name), &tarball).unwrap();
+
+        let integrity = IntegrityOpts::new()
+            .algorithm(Algorithm::Sha512)
+            .chain(&tarball)
+            .result();
+
+        let mut published = package.clone();
+        published["dist"] = json!({
+            "tarball": format!("{}{}/-/{}", self.url, name, file_name),
+            "integrity": integrity.to_string(),
+        });
+
+        let metadata_path = self.dir.path().join(metadata_name(name));
+        let mut versions: BTreeMap<String, Value> = std::fs::read(&metadata_path)
+            .ok()
+            .and_then(|metadata| serde_json::from_slice::<Value>(&metadata).ok())
+            .and_then(|metadata| serde_json::from_value(metadata["versions"].clone()).ok())
+            .unwrap_or_default();
+        versions.insert(version.to_string(), published);
+
+        let latest = versions
+            .keys()
+            .max_by_key(|version| semver_key(version))
+            .unwrap()
+            .clone();
+        let metadata = json!({
+            "name": name,
+            "dist-tags": { "latest": latest },
+            "versions": versions,
+        });
+        std::fs::write(metadata_path, metadata.to_string()).unwrap();
+    }
+}
+
+/// File answering the request for `path`: metadata at `/<name>` (scoped
+/// names having their `/` escaped or not), tarballs at `/<name>/-/<file>`.
+fn file_path(root: &Path, path: &str) -> PathBuf {
+    let path = path
+        .trim_start_matches('/')
+        .replace("%2f", "/")
+        .replace("%2F", "/");
+
+    match path.split_once("/-/") {
+        Some((_, file_name)) => root.join("tarballs").join(file_name),
+        None => root.join(metadata_name(&path)),
+    }
+}
+
+fn metadata_name(name: &str) -> String {
+    format!("{}.json", name.replace('/', "%"))
+}
+
+fn semver_key(version: &str) -> Vec<u64> {
+    version
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .filter_map(|part| part.parse().ok())
+        .collect()
+}
@@ -0,0 +1,76 @@
+mod common;
+mod registry;
+
+use std::path::Path;
+
+use registry::Registry;
+
+fn read(dir: &Path, path: &str) -> String {
+    std::fs::read_to_string(dir.join(path)).unwrap()
+}
+
+#[test]
+fn prunes_orphaned_dependencies() {
+    let registry = Registry::new();
+    registry.publish(
+        r#"{"name":"a","version":"1.0.0","dependencies":{"b":"^1.0.0","c":"^1.0.0"}}"#,
+        &[],
+    );
+    registry.publish(r#"{"name":"b","version":"1.0.0"}"#, &[]);
+    registry.publish(r#"{"name":"c","version":"1.0.0"}"#, &[]);
+    registry.publish(
+        r#"{"name":"d","version":"1.0.0","dependencies":{"c":"^1.0.0"}}"#,
+        &[],
+    );
+
+    let project = common::project(
+        r#"{"name":"app","version":"1.0.0","dependencies":{"a":"^1.0.0","d":"^1.0.0"}}"#,
+    );
+
+    let output = common::nana(project.path())
+        .env("NPM_CONFIG_REGISTRY", registry.url())
+        .arg("install")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(project.path().join("node_modules/b").exists());
+
+    let output = common::nana(project.path())
+        .env("NPM_CONFIG_REGISTRY", registry.url())
+        .args(["remove", "a"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    assert!(!read(project.path(), "package.json").contains("\"a\""));
+
+    // `b` was only required by `a`, `c` is still required by `d`
+    let lock = read(project.path(), "nana.lock.yml");
+    assert!(!lock.contains("node_modules/a:"), "{}", lock);
+    assert!(!lock.contains("node_modules/b:"), "{}", lock);
+    assert!(lock.contains("node_modules/c:"), "{}", lock);
+    assert!(lock.contains("node_modules/d:"), "{}", lock);
+
+    for (name, installed) in [("a", false), ("b", false), ("c", true), ("d", true)] {
+        let path = project.path().join("node_modules").join(name);
+        assert_eq!(path.exists(), installed, "{}", name);
+    }
+}
+
+#[test]
+fn fails_for_unknown_dependency() {
+    let manifest = r#"{"name":"app","version":"1.0.0","dependencies":{}}"#;
+    let project = common::project(manifest);
+
+    let output = common::nana(project.path())
+        .args(["remove", "missing"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{:?}", output);
+    let printed = [output.stdout, output.stderr].concat();
+    assert!(
+        String::from_utf8_lossy(&printed).contains("'missing' is not a dependency of this package")
+    );
+
+    assert_eq!(read(project.path(), "package.json"), manifest);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::path::Path;
+
+fn write(dir: &Path, path: &str, content: &str) {
+    let path = dir.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+#[test]
+fn runs_root_script_with_hooks() {
+    let project = common::project(
+        r#"{
+            "name": "app",
+            "version": "1.0.0",
+            "scripts": {
+                "prehello": "echo pre > log",
+                "hello": "echo \"$npm_lifecycle_event\" >> log && echo",
+                "posthello": "echo post >> log"
+            }
+        }"#,
+    );
+
+    let output = common::nana(project.path())
+        .args(["run", "hello", "--", "a b"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    // Arguments are passed through as is
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line == "a b"));
+
+    let log = std::fs::read_to_string(project.path().join("log")).unwrap();
+    assert_eq!(log, "pre\nhello\npost\n");
+
+    // Unknown commands run the script of the same name
+    let output = common::nana(project.path()).arg("hello").output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn waits_for_workspaces_reached_through_others() {
+    let project = common::project(
+        r#"{"name":"mono","version":"1.0.0","private":true,"workspaces":["packages/*"]}"#,
+    );
+
+    // `a` depends on `c` through `b`, which has nothing to build
+    write(
+        project.path(),
+        "packages/a/package.json",
+        r#"{"name":"a","version":"1.0.0","dependencies":{"b":"*"},"scripts":{"build":"echo a >> ../../log"}}"#,
+    );
+    write(
+        project.path(),
+        "packages/b/package.json",
+        r#"{"name":"b","version":"1.0.0","dependencies":{"c":"*"}}"#,
+    );
+    write(
+        project.path(),
+        "packages/c/package.json",
+        r#"{"name":"c","version":"1.0.0","scripts":{"build":"sleep 1 && echo c >> ../../log"}}"#,
+    );
+
+    let output = common::nana(project.path())
+        .args(["run", "build", "-r"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let log = std::fs::read_to_string(project.path().join("log")).unwrap();
+    assert_eq!(log, "c\na\n");
+}